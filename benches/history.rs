@@ -0,0 +1,118 @@
+// compares the undo history against the way it used to be stored, an (index, before, after)
+// for every pixel a stroke touched. run with `cargo bench`
+#![allow(dead_code)]
+
+#[path = "../src/modules/history.rs"]
+mod history;
+
+use std::{collections::BTreeMap, hint::black_box, mem::size_of, time::Instant};
+
+use history::{PixelChange, PixelDiff};
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+
+/// How many times the timed parts run, the fastest run is reported
+const ROUNDS: usize = 20;
+
+/// Every pixel a brush of `radius` covers going along a wavy line across the screen.
+/// With `smooth` the edge pixels get a color of their own, like antialiased strokes do
+fn stroke(radius: i32, smooth: bool, background: u32) -> Vec<PixelChange> {
+    let mut pixels = BTreeMap::new();
+
+    for step in 0..400 {
+        let t = step as f64 / 400.0;
+        let cx = (100.0 + t * 1700.0) as i32;
+        let cy = (540.0 + (t * 12.0).sin() * 300.0) as i32;
+
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let d = ((x * x + y * y) as f64).sqrt();
+                if d > radius as f64 {
+                    continue;
+                }
+
+                let (px, py) = (cx + x, cy + y);
+                if px < 0 || py < 0 || px as u32 >= WIDTH || py as u32 >= HEIGHT {
+                    continue;
+                }
+
+                let alpha = match smooth {
+                    true => ((radius as f64 - d).clamp(0.0, 1.0) * 255.0) as u32,
+                    false => 255,
+                };
+                let color = (alpha << 24) | 0x00ffccaa;
+
+                let entry = pixels.entry(px as u32 + py as u32 * WIDTH).or_insert(color);
+                *entry = (*entry).max(color);
+            }
+        }
+    }
+
+    pixels
+        .into_iter()
+        .map(|(buf_index, after_data)| PixelChange {
+            buf_index,
+            before_data: background,
+            after_data,
+        })
+        .collect()
+}
+
+fn fastest(mut f: impl FnMut()) -> f64 {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed().as_secs_f64() * 1000.0
+        })
+        .fold(f64::MAX, f64::min)
+}
+
+fn main() {
+    println!(
+        "{:<22} {:>9} {:>12} {:>12} {:>7} {:>10} {:>10} {:>10}",
+        "stroke", "pixels", "old bytes", "new bytes", "ratio", "build ms", "old undo", "new undo"
+    );
+
+    let cases = [
+        ("hard, size 2", 2, false, 0),
+        ("hard, size 10", 10, false, 0),
+        ("smooth, size 10", 10, true, 0),
+        ("hard, size 40", 40, false, 0),
+        ("smooth, size 40", 40, true, 0),
+        ("whiteboard, size 10", 10, false, 0xffffffff),
+    ];
+
+    for (name, radius, smooth, background) in cases {
+        let changes = stroke(radius, smooth, background);
+        let diff = PixelDiff::from_changes(changes.iter().copied());
+
+        let old_bytes = size_of::<Vec<PixelChange>>() + changes.len() * size_of::<PixelChange>();
+        let new_bytes = diff.bytes();
+
+        let build = fastest(|| {
+            black_box(PixelDiff::from_changes(black_box(&changes).iter().copied()));
+        });
+
+        let mut buffer = vec![0u32; (WIDTH * HEIGHT) as usize];
+        let old_undo = fastest(|| {
+            for change in black_box(&changes) {
+                buffer[change.buf_index as usize] = change.before_data;
+            }
+        });
+        let new_undo = fastest(|| black_box(&diff).apply(&mut buffer, false));
+
+        println!(
+            "{:<22} {:>9} {:>12} {:>12} {:>6.1}x {:>10.3} {:>10.3} {:>10.3}",
+            name,
+            changes.len(),
+            old_bytes,
+            new_bytes,
+            old_bytes as f64 / new_bytes as f64,
+            build,
+            old_undo,
+            new_undo,
+        );
+    }
+}
@@ -1,3 +1,9 @@
+use std::str::FromStr;
+
+use super::bindings::TriggerEvents;
+use crate::modules::stroke::Tool;
+use winit::event::ElementState;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Actions {
     CloseWindow,
@@ -12,11 +18,112 @@ pub enum Actions {
     ExitDrawMode,
     RedoDraw,
     UndoDraw,
+    NextBranch,
+    PreviousBranch,
+    ToggleBrushEdge,
+    ToggleEraser,
+    ToggleEraseMode,
+    SelectTool(Tool),
+    GrowBrush,
+    ShrinkBrush,
+    NextColor,
+    SaveImage,
+    SaveSvg,
+    CopyImage,
+    CopyInk,
+    PasteInk,
+    SaveSession,
+    ClearCanvas,
+    FillCanvas,
+    AddLayer,
+    NextLayer,
+    PreviousLayer,
+    MergeLayerDown,
+    ToggleLayerVisibility,
+    ToggleLayerLock,
+    RaiseLayerOpacity,
+    LowerLayerOpacity,
+    MoveLayerLeft,
+    MoveLayerRight,
+    MoveLayerUp,
+    MoveLayerDown,
+    SelectStroke,
+    RecolorStroke,
+    MoveStrokeLeft,
+    MoveStrokeRight,
+    MoveStrokeUp,
+    MoveStrokeDown,
 
     SetDrawing,
+    SetErasing,
 }
 
 impl Actions {
+    /// Every action, used to find ones that nothing is bound to
+    pub const ALL: &'static [Actions] = &[
+        Actions::CloseWindow,
+        Actions::Minimize,
+        Actions::ToggleMaximize,
+        Actions::ToggleDecorations,
+        Actions::ToggleFullscreen,
+        Actions::ToggleDrawMode,
+        Actions::EnterDrawMode,
+        Actions::ExitDrawMode,
+        Actions::RedoDraw,
+        Actions::UndoDraw,
+        Actions::NextBranch,
+        Actions::PreviousBranch,
+        Actions::ToggleBrushEdge,
+        Actions::ToggleEraser,
+        Actions::ToggleEraseMode,
+        Actions::SelectTool(Tool::Pen),
+        Actions::SelectTool(Tool::Eraser),
+        Actions::SelectTool(Tool::Highlighter),
+        Actions::SelectTool(Tool::Line),
+        Actions::SelectTool(Tool::Rectangle),
+        Actions::SelectTool(Tool::Ellipse),
+        Actions::SelectTool(Tool::Arrow),
+        Actions::GrowBrush,
+        Actions::ShrinkBrush,
+        Actions::NextColor,
+        Actions::SaveImage,
+        Actions::SaveSvg,
+        Actions::CopyImage,
+        Actions::CopyInk,
+        Actions::PasteInk,
+        Actions::SaveSession,
+        Actions::ClearCanvas,
+        Actions::FillCanvas,
+        Actions::AddLayer,
+        Actions::NextLayer,
+        Actions::PreviousLayer,
+        Actions::MergeLayerDown,
+        Actions::ToggleLayerVisibility,
+        Actions::ToggleLayerLock,
+        Actions::RaiseLayerOpacity,
+        Actions::LowerLayerOpacity,
+        Actions::MoveLayerLeft,
+        Actions::MoveLayerRight,
+        Actions::MoveLayerUp,
+        Actions::MoveLayerDown,
+        Actions::SelectStroke,
+        Actions::RecolorStroke,
+        Actions::MoveStrokeLeft,
+        Actions::MoveStrokeRight,
+        Actions::MoveStrokeUp,
+        Actions::MoveStrokeDown,
+        Actions::SetDrawing,
+        Actions::SetErasing,
+    ];
+
+    /// Actions that last as long as the button is held fire on press and release, everything else on press
+    pub fn default_trigger(&self) -> TriggerEvents {
+        match self {
+            Actions::SetDrawing | Actions::SetErasing => TriggerEvents::Hold,
+            _ => TriggerEvents::OneTime(ElementState::Pressed),
+        }
+    }
+
     pub fn help(&self) -> &'static str {
         match self {
             Actions::CloseWindow => "Close window",
@@ -30,8 +137,126 @@ impl Actions {
             Actions::ExitDrawMode => "Exits draw mode",
             Actions::RedoDraw => "Redraws last undone action",
             Actions::UndoDraw => "Undoes last action",
+            Actions::NextBranch => {
+                "Swaps the last action for the next one that was done after undoing to the same point"
+            }
+            Actions::PreviousBranch => {
+                "Swaps the last action for the previous one that was done after undoing to the same point"
+            }
+            Actions::ToggleBrushEdge => "Switches the brush between hard and smooth edges",
+            Actions::ToggleEraser => "Switches between the pen and the eraser",
+            Actions::ToggleEraseMode => {
+                "Switches the eraser between erasing pixels and whole strokes"
+            }
+            Actions::SelectTool(_) => "Picks the tool used for drawing",
+            Actions::GrowBrush => "Makes the brush bigger",
+            Actions::ShrinkBrush => "Makes the brush smaller",
+            Actions::NextColor => "Switches to the next color in the palette",
+            Actions::SaveImage => "Saves the drawing as a png",
+            Actions::SaveSvg => "Saves the strokes as an svg that stays sharp at any size",
+            Actions::CopyImage => "Copies the whole canvas to the clipboard",
+            Actions::CopyInk => {
+                "Copies just the part of the canvas thats drawn on to the clipboard, and the strokes of the current layer for pasting"
+            }
+            Actions::PasteInk => "Puts the last copied strokes on the current layer",
+            Actions::SaveSession => {
+                "Saves the strokes and undo history so they can be opened again"
+            }
+            Actions::ClearCanvas => "Wipes everything off the layer being drawn on, can be undone",
+            Actions::FillCanvas => "Covers the layer being drawn on in the brush color",
+            Actions::AddLayer => "Adds an empty layer above the current one and switches to it",
+            Actions::NextLayer => "Switches to the layer above",
+            Actions::PreviousLayer => "Switches to the layer below",
+            Actions::MergeLayerDown => "Puts the current layer on top of the one below it",
+            Actions::ToggleLayerVisibility => "Shows or hides the current layer",
+            Actions::ToggleLayerLock => "Locks the current layer so it cant be drawn on",
+            Actions::RaiseLayerOpacity => "Makes the current layer more opaque",
+            Actions::LowerLayerOpacity => "Makes the current layer more see through",
+            Actions::MoveLayerLeft => "Moves everything on the current layer to the left",
+            Actions::MoveLayerRight => "Moves everything on the current layer to the right",
+            Actions::MoveLayerUp => "Moves everything on the current layer up",
+            Actions::MoveLayerDown => "Moves everything on the current layer down",
+            Actions::SelectStroke => {
+                "Picks the stroke under the cursor for moving or recoloring, or lets go of it over nothing"
+            }
+            Actions::RecolorStroke => "Gives the picked stroke the brush color",
+            Actions::MoveStrokeLeft => "Moves the picked stroke to the left",
+            Actions::MoveStrokeRight => "Moves the picked stroke to the right",
+            Actions::MoveStrokeUp => "Moves the picked stroke up",
+            Actions::MoveStrokeDown => "Moves the picked stroke down",
 
             Actions::SetDrawing => "Starts drawing when cursor moved",
+            Actions::SetErasing => "Starts erasing when cursor moved",
         }
     }
 }
+
+impl FromStr for Actions {
+    type Err = String;
+
+    /// Parses the variant name, tools are picked with `SelectTool(Line)`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let action = match s.trim() {
+            "CloseWindow" => Actions::CloseWindow,
+            "Minimize" => Actions::Minimize,
+            "ToggleMaximize" => Actions::ToggleMaximize,
+            "ToggleDecorations" => Actions::ToggleDecorations,
+            "ToggleFullscreen" => Actions::ToggleFullscreen,
+
+            "ToggleDrawMode" => Actions::ToggleDrawMode,
+
+            "EnterDrawMode" => Actions::EnterDrawMode,
+            "ExitDrawMode" => Actions::ExitDrawMode,
+            "RedoDraw" => Actions::RedoDraw,
+            "UndoDraw" => Actions::UndoDraw,
+            "NextBranch" => Actions::NextBranch,
+            "PreviousBranch" => Actions::PreviousBranch,
+            "ToggleBrushEdge" => Actions::ToggleBrushEdge,
+            "ToggleEraser" => Actions::ToggleEraser,
+            "ToggleEraseMode" => Actions::ToggleEraseMode,
+            "GrowBrush" => Actions::GrowBrush,
+            "ShrinkBrush" => Actions::ShrinkBrush,
+            "NextColor" => Actions::NextColor,
+            "SaveImage" => Actions::SaveImage,
+            "SaveSvg" => Actions::SaveSvg,
+            "CopyImage" => Actions::CopyImage,
+            "CopyInk" => Actions::CopyInk,
+            "PasteInk" => Actions::PasteInk,
+            "SaveSession" => Actions::SaveSession,
+            "ClearCanvas" => Actions::ClearCanvas,
+            "FillCanvas" => Actions::FillCanvas,
+            "AddLayer" => Actions::AddLayer,
+            "NextLayer" => Actions::NextLayer,
+            "PreviousLayer" => Actions::PreviousLayer,
+            "MergeLayerDown" => Actions::MergeLayerDown,
+            "ToggleLayerVisibility" => Actions::ToggleLayerVisibility,
+            "ToggleLayerLock" => Actions::ToggleLayerLock,
+            "RaiseLayerOpacity" => Actions::RaiseLayerOpacity,
+            "LowerLayerOpacity" => Actions::LowerLayerOpacity,
+            "MoveLayerLeft" => Actions::MoveLayerLeft,
+            "MoveLayerRight" => Actions::MoveLayerRight,
+            "MoveLayerUp" => Actions::MoveLayerUp,
+            "MoveLayerDown" => Actions::MoveLayerDown,
+            "SelectStroke" => Actions::SelectStroke,
+            "RecolorStroke" => Actions::RecolorStroke,
+            "MoveStrokeLeft" => Actions::MoveStrokeLeft,
+            "MoveStrokeRight" => Actions::MoveStrokeRight,
+            "MoveStrokeUp" => Actions::MoveStrokeUp,
+            "MoveStrokeDown" => Actions::MoveStrokeDown,
+
+            "SetDrawing" => Actions::SetDrawing,
+            "SetErasing" => Actions::SetErasing,
+
+            other => {
+                let tool = other
+                    .strip_prefix("SelectTool(")
+                    .and_then(|t| t.strip_suffix(')'))
+                    .ok_or_else(|| format!("unknown action \"{other}\""))?;
+
+                Actions::SelectTool(tool.parse()?)
+            }
+        };
+
+        Ok(action)
+    }
+}
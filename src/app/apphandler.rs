@@ -1,6 +1,6 @@
 use crate::modules::dmodifiers::DModifiers;
 
-use super::bindings::TriggerEvents;
+use super::bindings::{key_name, TriggerEvents, WheelDirection};
 use super::windowstate::WindowState;
 use super::{bindings::Binding, program::Application};
 use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, TouchPhase};
@@ -12,6 +12,7 @@ use winit::{application::ApplicationHandler, event::WindowEvent};
 pub enum TrayEvent {
     RightClick,
 
+    Show,
     Exit,
 }
 
@@ -27,6 +28,7 @@ pub enum UserEvent {
     Redraw,
 
     StartMinimized,
+    StartDrawMode,
 
     TrayEvent(TrayEvent),
 }
@@ -36,6 +38,10 @@ impl ApplicationHandler<UserEvent> for Application {
         match event {
             UserEvent::WakeUp => {}
             UserEvent::Redraw => {
+                self.update_sequence_hint();
+                self.tick_bindings(_event_loop);
+                self.check_config_files();
+
                 // dbg!("Requesting redraw");
                 self.windows.values_mut().for_each(|window| {
                     window.window.request_redraw();
@@ -43,17 +49,31 @@ impl ApplicationHandler<UserEvent> for Application {
             }
 
             UserEvent::StartMinimized => {
+                let hide = self.can_hide();
+
                 self.windows.values_mut().for_each(|window| {
-                    window.minimize();
+                    window.minimize(hide);
                     window.draw_mode = false
                 });
             }
 
+            UserEvent::StartDrawMode => {
+                self.windows
+                    .values_mut()
+                    .for_each(|window| window.enter_draw_mode());
+            }
+
             UserEvent::TrayEvent(t) => match t {
                 TrayEvent::RightClick => {
                     self.tray.show_menu().unwrap();
                 }
 
+                TrayEvent::Show => {
+                    self.windows
+                        .values_mut()
+                        .for_each(|window| window.enter_draw_mode());
+                }
+
                 TrayEvent::Exit => {
                     _event_loop.exit();
                 }
@@ -80,11 +100,22 @@ impl ApplicationHandler<UserEvent> for Application {
             WindowEvent::Resized(size) => window.resize(size),
 
             WindowEvent::CloseRequested => {
-                self.windows.remove(&window_id);
+                self.close_window(window_id);
+            }
+
+            WindowEvent::Focused(true) if window.restored() && !window.draw_mode => {
+                window.enter_draw_mode();
+            }
+
+            // releases that happen while unfocused never arrive, so the next press has to start fresh
+            WindowEvent::Focused(false) => {
+                window.end_stroke();
+                self.bindings.reset();
+                self.keys.reset();
             }
 
             WindowEvent::ModifiersChanged(modifiers) => {
-                window.modifiers = modifiers.state();
+                window.set_modifiers(modifiers.state());
             }
 
             WindowEvent::MouseWheel {
@@ -92,7 +123,17 @@ impl ApplicationHandler<UserEvent> for Application {
                 delta: MouseScrollDelta::LineDelta(_, y),
                 phase: _,
             } => {
-                window.canvas.change_brush_size(y as i32);
+                let direction = match y {
+                    y if y > 0.0 => WheelDirection::Up,
+                    y if y < 0.0 => WheelDirection::Down,
+                    _ => return,
+                };
+
+                let mods = window.modifiers;
+
+                if let Some(action) = self.process_wheel_binding(direction, &mods) {
+                    self.handle_action(event_loop, window_id, action);
+                }
             }
 
             //
@@ -101,9 +142,14 @@ impl ApplicationHandler<UserEvent> for Application {
                 state,
                 button,
             } => {
+                // windows also sends mouse events for the pen, which would end the stroke early
+                if window.pen_id.is_some() {
+                    return;
+                }
+
                 let mods = window.modifiers;
 
-                if let Some(action) = Self::process_mouse_binding(button, &mods, state) {
+                if let Some(action) = self.process_mouse_binding(button, &mods, state) {
                     self.handle_action(event_loop, window_id, action);
                 }
             }
@@ -116,17 +162,17 @@ impl ApplicationHandler<UserEvent> for Application {
                 let mods = window.modifiers;
                 let state = event.state;
 
-                let action = if let Key::Character(ch) = event.logical_key.as_ref() {
-                    Self::process_keyboard_binding(&ch.to_uppercase(), &mods, state)
-                } else {
-                    None
-                };
+                let action = key_name(&event.logical_key).and_then(|key| {
+                    self.process_keyboard_binding(&key, &mods, state, event.repeat)
+                });
 
                 if let Some(action) = action {
                     self.handle_action(event_loop, window_id, action);
                 }
             }
 
+            WindowEvent::Touch(touch) => window.pen_input(touch),
+
             WindowEvent::CursorMoved {
                 device_id: _,
                 position,
@@ -176,29 +222,7 @@ impl ApplicationHandler<UserEvent> for Application {
                     }
                 };
 
-                // i have a Small feeling i did this in a dumb way, Guess ill figure it out later :D
-                match self.keymap.get_mut(&keypressed) {
-                    None => {
-                        self.keymap.insert(keypressed, rke.state.is_pressed());
-                    }
-                    Some(v) => match v {
-                        false => {
-                            if rke.state.is_pressed() {
-                                *v = true;
-                            }
-                        }
-                        true => {
-                            if rke.state.is_pressed() {
-                                return;
-                            } else {
-                                *v = false
-                            }
-                        }
-                    },
-                };
-
-                if let Some(action) =
-                    Self::process_device_binding(keypressed, self.dmods, rke.state)
+                if let Some(action) = self.process_device_binding(keypressed, self.dmods, rke.state)
                 {
                     self.handle_action(event_loop, wid, action);
                 };
@@ -219,6 +243,12 @@ impl ApplicationHandler<UserEvent> for Application {
     }
 
     fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Some(window_id) = self.windows.keys().next().copied() {
+            self.sync_settings(window_id);
+        }
+
+        self.windows.values().for_each(|window| window.autosave());
+
         self.context = None;
     }
 }
@@ -1,34 +1,85 @@
+use std::time::{Duration, Instant};
+
 use super::actions::Actions;
+use crate::modules::stroke::Tool;
 use winit::{
     event::{ElementState, MouseButton},
-    keyboard::{KeyCode, ModifiersState},
+    keyboard::{Key, KeyCode, ModifiersState, NamedKey},
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TriggerEvents {
+    /// Fires on every press and release, for actions that flip something on and back off
     Toggle,
+    /// Fires once on the chosen press or release
     OneTime(ElementState),
+    /// Fires on press and again on release, the release counts even if the modifiers were let go first
+    Hold,
+    /// Fires on the second press when it comes within `DOUBLE_TAP_TIME` of the first
+    DoubleTap,
+    /// Fires once the trigger has been held down for `LONG_PRESS_TIME`
+    LongPress,
 }
 
 impl TriggerEvents {
-    pub fn inner(&self) -> &ElementState {
+    /// The state a one time trigger waits for, the others care about more than one event
+    pub fn inner(&self) -> Option<&ElementState> {
         match self {
-            Self::Toggle => unreachable!(),
-            Self::OneTime(i) => i,
+            Self::OneTime(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Which kinds of events fire the binding, as `EVENT_*` flags
+    fn events(&self) -> u8 {
+        match self {
+            Self::Toggle | Self::Hold => EVENT_PRESS | EVENT_RELEASE | EVENT_DOUBLE_TAP,
+            Self::OneTime(ElementState::Pressed) => EVENT_PRESS | EVENT_DOUBLE_TAP,
+            Self::OneTime(ElementState::Released) => EVENT_RELEASE,
+            Self::DoubleTap => EVENT_DOUBLE_TAP,
+            Self::LongPress => EVENT_LONG_PRESS,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+const EVENT_PRESS: u8 = 1;
+const EVENT_RELEASE: u8 = 1 << 1;
+/// a double tap is also a press, so anything firing on presses catches it too
+const EVENT_DOUBLE_TAP: u8 = 1 << 2;
+const EVENT_LONG_PRESS: u8 = 1 << 3;
+
+/// Longest gap between the two presses of a double tap
+pub const DOUBLE_TAP_TIME: Duration = Duration::from_millis(300);
+/// How long a trigger has to be held for a long press
+pub const LONG_PRESS_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WheelDirection {
+    Up,
+    Down,
+}
+
+/// How long a key sequence waits for its next key
+pub const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Debug)]
 pub struct Binding<T: Eq> {
     trigger: T,
     mods: Option<ModifiersState>,
 
+    /// Keys that have to be pressed in order before `trigger`, empty for normal bindings
+    prefix: Vec<(T, Option<ModifiersState>)>,
+
     conditions: TriggerEvents,
 
     pub action: Actions,
 
     is_pressed: bool,
+    pressed_at: Option<Instant>,
+    /// First press of a possible double tap
+    last_tap: Option<Instant>,
+    /// Long press already fired for the current press
+    long_pressed: bool,
 }
 
 impl<T: Eq> Binding<T> {
@@ -41,41 +92,503 @@ impl<T: Eq> Binding<T> {
         Self {
             trigger,
             mods,
+            prefix: Vec::new(),
             action,
             conditions,
             is_pressed: false,
+            pressed_at: None,
+            last_tap: None,
+            long_pressed: false,
         }
     }
 
-    pub fn is_triggered_by(
+    fn with_prefix(mut self, prefix: Vec<(T, Option<ModifiersState>)>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    fn map<U: Eq>(self, f: impl Fn(T) -> U) -> Binding<U> {
+        Binding {
+            trigger: f(self.trigger),
+            mods: self.mods,
+            prefix: self.prefix.into_iter().map(|(t, m)| (f(t), m)).collect(),
+            conditions: self.conditions,
+            action: self.action,
+            is_pressed: self.is_pressed,
+            pressed_at: self.pressed_at,
+            last_tap: self.last_tap,
+            long_pressed: self.long_pressed,
+        }
+    }
+
+    /// Whether the event matches the binding, without looking at timing.
+    /// Double taps and long presses need `handle` and `tick` instead
+    pub fn is_triggered_by<Q: ?Sized>(
         &self,
-        trigger: &T,
+        pending: &[(T, ModifiersState)],
+        trigger: &Q,
         mods: &ModifiersState,
         state: &ElementState,
-    ) -> bool {
+    ) -> bool
+    where
+        T: PartialEq<Q>,
+    {
         let condition = match self.conditions {
-            TriggerEvents::Toggle => true,
+            TriggerEvents::Toggle | TriggerEvents::Hold => true,
             TriggerEvents::OneTime(evnt) => &evnt == state,
+            TriggerEvents::DoubleTap | TriggerEvents::LongPress => false,
         };
 
-        let mods_condition = match self.mods {
+        self.trigger == *trigger
+            && self.mods_match(mods)
+            && condition
+            && self.prefix_matches(pending)
+    }
+
+    /// Whether a plain press of `trigger` would fire this binding, sequences need more than that
+    fn fires_on_press<Q: ?Sized>(&self, trigger: &Q, mods: &ModifiersState) -> bool
+    where
+        T: PartialEq<Q>,
+    {
+        self.trigger == *trigger
+            && self.mods_match(mods)
+            && self.prefix.is_empty()
+            && self.conditions.events() & EVENT_PRESS != 0
+    }
+
+    fn mods_match(&self, mods: &ModifiersState) -> bool {
+        match self.mods {
             None => {
                 // Allow any set of modifiers to pass if not explicitly set
                 true
             }
             Some(m) => &m == mods,
-        };
+        }
+    }
+
+    /// Feeds an event to the binding, keeping track of how long its been held and when it was last tapped.
+    /// Returns whether the binding fires
+    pub fn handle<Q: ?Sized>(
+        &mut self,
+        pending: &[(T, ModifiersState)],
+        trigger: &Q,
+        mods: &ModifiersState,
+        state: &ElementState,
+    ) -> bool
+    where
+        T: PartialEq<Q>,
+    {
+        if self.trigger != *trigger {
+            return false;
+        }
+
+        let now = Instant::now();
+        let matches = self.mods_match(mods) && self.prefix_matches(pending);
+
+        match state {
+            ElementState::Pressed => {
+                // auto repeat
+                if self.is_pressed || !matches {
+                    return false;
+                }
+
+                self.is_pressed = true;
+                self.pressed_at = Some(now);
+                self.long_pressed = false;
+
+                match self.conditions {
+                    TriggerEvents::DoubleTap => {
+                        let double = self
+                            .last_tap
+                            .is_some_and(|tap| now.duration_since(tap) <= DOUBLE_TAP_TIME);
+                        self.last_tap = (!double).then_some(now);
 
-        &self.trigger == trigger && mods_condition && condition
+                        double
+                    }
+                    TriggerEvents::LongPress => false,
+                    conditions => conditions.events() & EVENT_PRESS != 0,
+                }
+            }
+            ElementState::Released => {
+                let was_pressed = std::mem::take(&mut self.is_pressed);
+                self.pressed_at = None;
+
+                match self.conditions {
+                    TriggerEvents::Hold => was_pressed,
+                    // these never needed the press, the release alone is enough
+                    TriggerEvents::Toggle | TriggerEvents::OneTime(ElementState::Released) => {
+                        was_pressed || matches
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    /// Checks timing that doesnt come with an event, returns whether a long press just fired
+    pub fn tick(&mut self) -> bool {
+        let held_long = self
+            .pressed_at
+            .is_some_and(|at| at.elapsed() >= LONG_PRESS_TIME);
+
+        if self.conditions != TriggerEvents::LongPress || !held_long || self.long_pressed {
+            return false;
+        }
+
+        self.long_pressed = true;
+        true
+    }
+
+    /// Forgets the binding is held, for when the release wont ever arrive
+    pub fn reset(&mut self) {
+        self.is_pressed = false;
+        self.pressed_at = None;
+    }
+
+    /// Whether pressing `trigger` after `pending` gets further into this bindings sequence
+    pub fn is_continued_by<Q: ?Sized>(
+        &self,
+        pending: &[(T, ModifiersState)],
+        trigger: &Q,
+        mods: &ModifiersState,
+    ) -> bool
+    where
+        T: PartialEq<Q>,
+    {
+        self.prefix.len() > pending.len()
+            && self.prefix_starts_with(pending)
+            && step_matches(&self.prefix[pending.len()], trigger, mods)
+    }
+
+    fn prefix_matches(&self, pending: &[(T, ModifiersState)]) -> bool {
+        self.prefix.len() == pending.len() && self.prefix_starts_with(pending)
+    }
+
+    fn prefix_starts_with(&self, pending: &[(T, ModifiersState)]) -> bool {
+        self.prefix.len() >= pending.len()
+            && (self.prefix.iter().zip(pending))
+                .all(|(step, (key, mods))| step_matches(step, key, mods))
+    }
+
+    /// Every step of the binding, the prefix followed by the trigger
+    fn steps(&self) -> impl Iterator<Item = (&T, Option<ModifiersState>)> {
+        (self.prefix.iter().map(|(t, m)| (t, *m)))
+            .chain(std::iter::once((&self.trigger, self.mods)))
     }
 }
 
-pub const MOUSE_BINDINGS: &[Binding<MouseButton>] = &[Binding::new(
-    MouseButton::Left,
-    None,
-    Actions::SetDrawing,
-    TriggerEvents::Toggle,
-)];
+fn step_matches<T: PartialEq<Q>, Q: ?Sized>(
+    step: &(T, Option<ModifiersState>),
+    key: &Q,
+    mods: &ModifiersState,
+) -> bool {
+    step.0 == *key && step.1.is_none_or(|m| &m == mods)
+}
+
+/// Whether every press that matches step `b` also matches step `a`
+fn step_covers<T: Eq>(a: (&T, Option<ModifiersState>), b: (&T, Option<ModifiersState>)) -> bool {
+    let mods = match (a.1, b.1) {
+        (None, _) => true,
+        (Some(a), Some(b)) => a == b,
+        (Some(_), None) => false,
+    };
+
+    a.0 == b.0 && mods
+}
+
+/// Keys held down and the start of a sequence that hasnt finished yet
+#[derive(Debug)]
+pub struct KeyState<T> {
+    pub pending: Vec<(T, ModifiersState)>,
+    since: Option<Instant>,
+
+    /// Pressing one of these again is just auto repeat
+    held: Vec<T>,
+}
+
+impl<T> Default for KeyState<T> {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            since: None,
+            held: Vec::new(),
+        }
+    }
+}
+
+impl<T> KeyState<T> {
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+        self.since = None;
+    }
+
+    /// Forgets about held keys too, for when their releases wont arrive
+    pub fn reset(&mut self) {
+        self.clear_pending();
+        self.held.clear();
+    }
+
+    fn push(&mut self, key: T, mods: ModifiersState) {
+        self.pending.push((key, mods));
+        self.since = Some(Instant::now());
+    }
+
+    /// The timeout counts from the last key, not the start of the sequence
+    pub fn is_expired(&self, timeout: Duration) -> bool {
+        self.since.is_some_and(|since| since.elapsed() > timeout)
+    }
+
+    /// What to show on screen while waiting for the rest of the sequence
+    pub fn hint(&self) -> Option<String>
+    where
+        T: TriggerName,
+    {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let keys: Vec<String> = (self.pending.iter())
+            .map(|(key, mods)| step_string(key, Some(*mods)))
+            .collect();
+
+        Some(format!("{} ...", keys.join(" ")))
+    }
+}
+
+/// Feeds an event to every binding in `table`, the first one that fires wins.
+/// All of them see the event so they all keep track of whats held
+pub fn process_event<T, Q>(
+    table: &mut [Binding<T>],
+    pending: &[(T, ModifiersState)],
+    trigger: &Q,
+    mods: &ModifiersState,
+    state: &ElementState,
+) -> Option<Actions>
+where
+    T: Eq + PartialEq<Q>,
+    Q: ?Sized,
+{
+    let mut fired = None;
+
+    for binding in table.iter_mut() {
+        if binding.handle(pending, trigger, mods, state) && fired.is_none() {
+            fired = Some(binding.action);
+        }
+    }
+
+    fired
+}
+
+/// Long presses that fired since the last tick
+pub fn tick_bindings<T: Eq>(table: &mut [Binding<T>]) -> Vec<Actions> {
+    table
+        .iter_mut()
+        .filter_map(|binding| binding.tick().then_some(binding.action))
+        .collect()
+}
+
+fn continues_sequence<T, Q>(
+    table: &[Binding<T>],
+    pending: &[(T, ModifiersState)],
+    key: &Q,
+    mods: &ModifiersState,
+) -> bool
+where
+    T: Eq + PartialEq<Q>,
+    Q: ?Sized,
+{
+    table
+        .iter()
+        .any(|binding| binding.is_continued_by(pending, key, mods))
+}
+
+/// Finds what a key does, following key sequences. Keys that start or continue a sequence
+/// are held back until it either finishes, times out or gets a key that doesnt fit.
+/// `repeat` marks presses the os sends while the key is held down
+pub fn process_key<T, Q>(
+    table: &mut [Binding<T>],
+    keys: &mut KeyState<T>,
+    timeout: Duration,
+    key: &Q,
+    mods: &ModifiersState,
+    state: &ElementState,
+    repeat: bool,
+) -> Option<Actions>
+where
+    T: Eq + PartialEq<Q>,
+    Q: ?Sized + ToOwned<Owned = T>,
+{
+    if keys.is_expired(timeout) {
+        keys.clear_pending();
+    }
+
+    // holding undo should keep undoing, but everything that tracks presses only sees the first one
+    if repeat && state.is_pressed() {
+        if keys.is_pending() {
+            return None;
+        }
+
+        return table
+            .iter()
+            .find(|binding| binding.fires_on_press(key, mods))
+            .filter(|binding| binding.conditions == TriggerEvents::OneTime(ElementState::Pressed))
+            .map(|binding| binding.action);
+    }
+
+    match state {
+        ElementState::Pressed if keys.held.iter().any(|held| held == key) => return None,
+        ElementState::Pressed => keys.held.push(key.to_owned()),
+        ElementState::Released => keys.held.retain(|held| held != key),
+    }
+
+    if !keys.is_pending() {
+        if state.is_pressed() && continues_sequence(table, &[], key, mods) {
+            keys.push(key.to_owned(), *mods);
+            return None;
+        }
+
+        return process_event(table, &[], key, mods, state);
+    }
+
+    // releases of the keys in the sequence shouldnt cancel it, but whatever was held before still lets go
+    if !state.is_pressed() {
+        return process_event(table, &[], key, mods, state);
+    }
+
+    match process_event(table, &keys.pending, key, mods, state) {
+        Some(action) => {
+            keys.clear_pending();
+            Some(action)
+        }
+        None if continues_sequence(table, &keys.pending, key, mods) => {
+            keys.push(key.to_owned(), *mods);
+            None
+        }
+        None => {
+            keys.clear_pending();
+            None
+        }
+    }
+}
+
+impl<T: Eq + TriggerName> Binding<T> {
+    /// The binding written the way the config file takes it, like `Ctrl+Alt+D` or `Ctrl+K C`
+    pub fn trigger_string(&self) -> String {
+        let steps: Vec<String> = self
+            .steps()
+            .map(|(key, mods)| step_string(key, mods))
+            .collect();
+
+        steps.join(" ")
+    }
+
+    /// Whether this binding always fires before `other` gets a chance when both are pressed.
+    /// Only meaningful when this binding comes first in its table
+    fn shadows(&self, other: &Self) -> bool {
+        let conditions = self.conditions.events() & other.conditions.events() != 0;
+
+        self.prefix.len() == other.prefix.len()
+            && self
+                .steps()
+                .zip(other.steps())
+                .all(|(a, b)| step_covers(a, b))
+            && conditions
+    }
+
+    /// Whether `other` is the start of this bindings sequence, the sequence wins so `other` never fires
+    fn swallows(&self, other: &Self) -> bool {
+        self.prefix.len() > other.prefix.len()
+            && self
+                .prefix
+                .iter()
+                .zip(other.steps())
+                .all(|(a, b)| step_covers((&a.0, a.1), b))
+    }
+}
+
+fn step_string<T: TriggerName>(key: &T, mods: Option<ModifiersState>) -> String {
+    let mut parts = Vec::new();
+
+    match mods {
+        None => parts.push("*"),
+        Some(m) => {
+            for (modifier, name) in [
+                (ModifiersState::CONTROL, "Ctrl"),
+                (ModifiersState::ALT, "Alt"),
+                (ModifiersState::SHIFT, "Shift"),
+                (ModifiersState::SUPER, "Super"),
+            ] {
+                if m.contains(modifier) {
+                    parts.push(name);
+                }
+            }
+        }
+    }
+
+    let name = key.trigger_name();
+    parts.push(&name);
+
+    parts.join("+")
+}
+
+/// How a trigger is written in the config file
+pub trait TriggerName {
+    fn trigger_name(&self) -> String;
+}
+
+impl TriggerName for MouseButton {
+    fn trigger_name(&self) -> String {
+        match self {
+            MouseButton::Other(n) => format!("Mouse:{n}"),
+            button => format!("Mouse:{button:?}"),
+        }
+    }
+}
+
+impl TriggerName for WheelDirection {
+    fn trigger_name(&self) -> String {
+        format!("Wheel:{self:?}")
+    }
+}
+
+impl TriggerName for String {
+    fn trigger_name(&self) -> String {
+        self.clone()
+    }
+}
+
+impl TriggerName for KeyCode {
+    fn trigger_name(&self) -> String {
+        if let Some((name, _, _)) = NAMED_KEYS.iter().find(|(_, _, code)| code == self) {
+            return name.to_string();
+        }
+
+        let name = format!("{self:?}");
+        match name.strip_prefix("Key").or(name.strip_prefix("Digit")) {
+            Some(key) => key.to_string(),
+            None => name,
+        }
+    }
+}
+
+pub const MOUSE_BINDINGS: &[Binding<MouseButton>] = &[
+    Binding::new(
+        MouseButton::Left,
+        None,
+        Actions::SetDrawing,
+        TriggerEvents::Hold,
+    ),
+    Binding::new(
+        MouseButton::Right,
+        None,
+        Actions::SetErasing,
+        TriggerEvents::Hold,
+    ),
+];
 
 pub const KEYBOARD_BINDINGS: &[Binding<&'static str>] = &[
     Binding::new(
@@ -102,6 +615,234 @@ pub const KEYBOARD_BINDINGS: &[Binding<&'static str>] = &[
         Actions::RedoDraw,
         TriggerEvents::OneTime(ElementState::Pressed),
     ),
+    Binding::new(
+        "RIGHT",
+        Some(ModifiersState::ALT),
+        Actions::NextBranch,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "LEFT",
+        Some(ModifiersState::ALT),
+        Actions::PreviousBranch,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "A",
+        Some(ModifiersState::empty()),
+        Actions::ToggleBrushEdge,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "E",
+        Some(ModifiersState::empty()),
+        Actions::ToggleEraser,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "E",
+        Some(ModifiersState::SHIFT),
+        Actions::ToggleEraseMode,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "P",
+        Some(ModifiersState::empty()),
+        Actions::SelectTool(Tool::Pen),
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "L",
+        Some(ModifiersState::empty()),
+        Actions::SelectTool(Tool::Line),
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "R",
+        Some(ModifiersState::empty()),
+        Actions::SelectTool(Tool::Rectangle),
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "O",
+        Some(ModifiersState::empty()),
+        Actions::SelectTool(Tool::Ellipse),
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "V",
+        Some(ModifiersState::empty()),
+        Actions::SelectTool(Tool::Arrow),
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "H",
+        Some(ModifiersState::empty()),
+        Actions::SelectTool(Tool::Highlighter),
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "C",
+        Some(ModifiersState::empty()),
+        Actions::NextColor,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "S",
+        Some(ModifiersState::CONTROL),
+        Actions::SaveImage,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "S",
+        Some(ModifiersState::CONTROL.union(ModifiersState::ALT)),
+        Actions::SaveSvg,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "C",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::CopyInk,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "C",
+        Some(ModifiersState::CONTROL.union(ModifiersState::ALT)),
+        Actions::CopyImage,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "V",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::PasteInk,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "S",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::SaveSession,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "DELETE",
+        Some(ModifiersState::empty()),
+        Actions::ClearCanvas,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "DELETE",
+        Some(ModifiersState::SHIFT),
+        Actions::FillCanvas,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "N",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::AddLayer,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "UP",
+        Some(ModifiersState::ALT),
+        Actions::NextLayer,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "DOWN",
+        Some(ModifiersState::ALT),
+        Actions::PreviousLayer,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "E",
+        Some(ModifiersState::CONTROL),
+        Actions::MergeLayerDown,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "H",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::ToggleLayerVisibility,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "L",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::ToggleLayerLock,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "UP",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::RaiseLayerOpacity,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "DOWN",
+        Some(ModifiersState::CONTROL.union(ModifiersState::SHIFT)),
+        Actions::LowerLayerOpacity,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "LEFT",
+        Some(ModifiersState::empty()),
+        Actions::MoveLayerLeft,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "RIGHT",
+        Some(ModifiersState::empty()),
+        Actions::MoveLayerRight,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "UP",
+        Some(ModifiersState::empty()),
+        Actions::MoveLayerUp,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "DOWN",
+        Some(ModifiersState::empty()),
+        Actions::MoveLayerDown,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "S",
+        Some(ModifiersState::empty()),
+        Actions::SelectStroke,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "C",
+        Some(ModifiersState::SHIFT),
+        Actions::RecolorStroke,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "LEFT",
+        Some(ModifiersState::SHIFT),
+        Actions::MoveStrokeLeft,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "RIGHT",
+        Some(ModifiersState::SHIFT),
+        Actions::MoveStrokeRight,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "UP",
+        Some(ModifiersState::SHIFT),
+        Actions::MoveStrokeUp,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        "DOWN",
+        Some(ModifiersState::SHIFT),
+        Actions::MoveStrokeDown,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
 ];
 
 pub const DEVICE_BINDINGS: &[Binding<KeyCode>] = &[Binding::new(
@@ -110,3 +851,442 @@ pub const DEVICE_BINDINGS: &[Binding<KeyCode>] = &[Binding::new(
     Actions::ToggleDrawMode,
     TriggerEvents::OneTime(ElementState::Pressed),
 )];
+
+pub const WHEEL_BINDINGS: &[Binding<WheelDirection>] = &[
+    Binding::new(
+        WheelDirection::Up,
+        None,
+        Actions::GrowBrush,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+    Binding::new(
+        WheelDirection::Down,
+        None,
+        Actions::ShrinkBrush,
+        TriggerEvents::OneTime(ElementState::Pressed),
+    ),
+];
+
+/// Every binding the app listens to, starts out as the defaults above
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    pub mouse: Vec<Binding<MouseButton>>,
+    pub wheel: Vec<Binding<WheelDirection>>,
+    pub keyboard: Vec<Binding<String>>,
+    /// Listened to even while the window isnt focused
+    pub device: Vec<Binding<KeyCode>>,
+
+    pub sequence_timeout: Duration,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            mouse: MOUSE_BINDINGS.to_vec(),
+            wheel: WHEEL_BINDINGS.to_vec(),
+            keyboard: KEYBOARD_BINDINGS
+                .iter()
+                .map(|b| b.clone().map(|t| t.to_string()))
+                .collect(),
+            device: DEVICE_BINDINGS.to_vec(),
+            sequence_timeout: SEQUENCE_TIMEOUT,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn empty() -> Self {
+        Self {
+            mouse: Vec::new(),
+            wheel: Vec::new(),
+            keyboard: Vec::new(),
+            device: Vec::new(),
+            sequence_timeout: SEQUENCE_TIMEOUT,
+        }
+    }
+
+    /// Lets go of everything, for when the window loses focus and wont see the releases
+    pub fn reset(&mut self) {
+        self.mouse.iter_mut().for_each(Binding::reset);
+        self.keyboard.iter_mut().for_each(Binding::reset);
+    }
+
+    /// Drops every binding for `action`, used when the config rebinds it
+    pub fn unbind(&mut self, action: Actions) {
+        self.mouse.retain(|b| b.action != action);
+        self.wheel.retain(|b| b.action != action);
+        self.keyboard.retain(|b| b.action != action);
+        self.device.retain(|b| b.action != action);
+    }
+
+    /// Adds every binding in `other`, the actions it binds lose whatever they were bound to before
+    pub fn replace(&mut self, other: Bindings) {
+        for action in other.actions() {
+            self.unbind(action);
+        }
+
+        self.mouse.extend(other.mouse);
+        self.wheel.extend(other.wheel);
+        self.keyboard.extend(other.keyboard);
+        self.device.extend(other.device);
+    }
+
+    /// Every action something is bound to, with repeats
+    fn actions(&self) -> Vec<Actions> {
+        (self.mouse.iter().map(|b| b.action))
+            .chain(self.wheel.iter().map(|b| b.action))
+            .chain(self.keyboard.iter().map(|b| b.action))
+            .chain(self.device.iter().map(|b| b.action))
+            .collect()
+    }
+
+    /// Looks for duplicate and shadowed bindings and actions nothing triggers
+    pub fn validate(&self) -> Vec<BindingIssue> {
+        let mut issues = Vec::new();
+
+        table_issues(&self.mouse, &mut issues);
+        table_issues(&self.wheel, &mut issues);
+        table_issues(&self.keyboard, &mut issues);
+        table_issues(&self.device, &mut issues);
+
+        let bound = self.actions();
+
+        issues.extend(
+            Actions::ALL
+                .iter()
+                .filter(|action| !bound.contains(action))
+                .map(|action| BindingIssue::Unbound(*action)),
+        );
+
+        issues
+    }
+
+    /// Adds a binding from a trigger string like `Ctrl+Alt+D`, `Mouse:Right` or `Wheel:Up+Shift`.
+    /// Keys separated by spaces make a sequence, `Ctrl+K C` is Ctrl+K followed by C.
+    /// `global` keyboard bindings go through device events so they work without focus
+    pub fn add(
+        &mut self,
+        trigger: &str,
+        action: Actions,
+        conditions: TriggerEvents,
+        global: bool,
+    ) -> Result<(), String> {
+        let mut steps = trigger
+            .split_whitespace()
+            .map(parse_trigger)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (trigger, mods) = steps.pop().ok_or("empty trigger")?;
+
+        let prefix = steps
+            .into_iter()
+            .map(|(step, mods)| match step {
+                Trigger::Key(key) => Ok((key, mods)),
+                _ => Err("only keyboard keys can be used in sequences".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match trigger {
+            Trigger::Mouse(_) | Trigger::Wheel(_) if global => {
+                return Err("only keyboard bindings can be global".to_string());
+            }
+            Trigger::Mouse(_) | Trigger::Wheel(_) if !prefix.is_empty() => {
+                return Err("only keyboard keys can be used in sequences".to_string());
+            }
+            Trigger::Mouse(button) => self
+                .mouse
+                .push(Binding::new(button, mods, action, conditions)),
+            Trigger::Wheel(direction) => self
+                .wheel
+                .push(Binding::new(direction, mods, action, conditions)),
+            Trigger::Key(key) if global => {
+                let code = |key: &str| {
+                    parse_key_code(key)
+                        .ok_or_else(|| format!("\"{key}\" cant be used as a global key"))
+                };
+
+                let prefix = (prefix.iter())
+                    .map(|(key, mods)| Ok((code(key)?, *mods)))
+                    .collect::<Result<Vec<_>, String>>()?;
+
+                self.device
+                    .push(Binding::new(code(&key)?, mods, action, conditions).with_prefix(prefix));
+            }
+            Trigger::Key(key) => self
+                .keyboard
+                .push(Binding::new(key, mods, action, conditions).with_prefix(prefix)),
+        }
+
+        Ok(())
+    }
+}
+
+/// Something that makes a binding not work the way it reads
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BindingIssue {
+    /// Same trigger bound twice, the second one never fires
+    Duplicate {
+        trigger: String,
+        action: Actions,
+        kept: Actions,
+    },
+    /// An earlier binding catches the trigger first, like `*+E` before `Shift+E`
+    Shadowed {
+        trigger: String,
+        action: Actions,
+        by_trigger: String,
+        by: Actions,
+    },
+    /// Nothing triggers the action
+    Unbound(Actions),
+}
+
+impl BindingIssue {
+    /// Unbound actions are allowed, the others mean a binding cant ever fire
+    pub fn is_error(&self) -> bool {
+        !matches!(self, BindingIssue::Unbound(_))
+    }
+}
+
+impl std::fmt::Display for BindingIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingIssue::Duplicate {
+                trigger,
+                action,
+                kept,
+            } if action == kept => write!(f, "\"{trigger}\" is bound to {action:?} twice"),
+            BindingIssue::Duplicate {
+                trigger,
+                action,
+                kept,
+            } => write!(
+                f,
+                "\"{trigger}\" is bound to both {kept:?} and {action:?}, {action:?} never fires"
+            ),
+            BindingIssue::Shadowed {
+                trigger,
+                action,
+                by_trigger,
+                by,
+            } => write!(
+                f,
+                "\"{trigger}\" ({action:?}) never fires, \"{by_trigger}\" ({by:?}) catches it first"
+            ),
+            BindingIssue::Unbound(action) => write!(f, "{action:?} isnt bound to anything"),
+        }
+    }
+}
+
+fn table_issues<T: Eq + TriggerName>(table: &[Binding<T>], issues: &mut Vec<BindingIssue>) {
+    for (i, binding) in table.iter().enumerate() {
+        let Some(first) = (table[..i].iter().find(|earlier| earlier.shadows(binding)))
+            .or_else(|| table.iter().find(|other| other.swallows(binding)))
+        else {
+            continue;
+        };
+
+        let (trigger, by_trigger) = (binding.trigger_string(), first.trigger_string());
+
+        issues.push(
+            if trigger == by_trigger && first.conditions == binding.conditions {
+                BindingIssue::Duplicate {
+                    trigger,
+                    action: binding.action,
+                    kept: first.action,
+                }
+            } else {
+                BindingIssue::Shadowed {
+                    trigger,
+                    action: binding.action,
+                    by_trigger,
+                    by: first.action,
+                }
+            },
+        );
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Mouse(MouseButton),
+    Wheel(WheelDirection),
+    /// Key name as produced by `key_name`
+    Key(String),
+}
+
+/// Splits `Ctrl+Alt+D` style strings into the trigger and its modifiers, order doesnt matter.
+/// No modifiers means none may be held, a `*` allows any
+pub fn parse_trigger(s: &str) -> Result<(Trigger, Option<ModifiersState>), String> {
+    let mut mods = Some(ModifiersState::empty());
+    let mut trigger = None;
+
+    for part in s.split('+').map(str::trim) {
+        let modifier = match part.to_uppercase().as_str() {
+            "CTRL" | "CONTROL" => ModifiersState::CONTROL,
+            "ALT" => ModifiersState::ALT,
+            "SHIFT" => ModifiersState::SHIFT,
+            "SUPER" | "WIN" | "META" | "CMD" => ModifiersState::SUPER,
+            "*" => {
+                mods = None;
+                continue;
+            }
+            "" => return Err(format!("empty key in \"{s}\"")),
+            _ => {
+                if trigger.is_some() {
+                    return Err(format!("more than one key in \"{s}\""));
+                }
+
+                trigger = Some(parse_key(part)?);
+                continue;
+            }
+        };
+
+        mods = mods.map(|m| m | modifier);
+    }
+
+    let trigger = trigger.ok_or_else(|| format!("no key in \"{s}\""))?;
+
+    Ok((trigger, mods))
+}
+
+fn parse_key(part: &str) -> Result<Trigger, String> {
+    let prefixed = |prefix: &str| {
+        part.get(..prefix.len())
+            .filter(|p| p.eq_ignore_ascii_case(prefix))
+            .map(|_| &part[prefix.len()..])
+    };
+
+    if let Some(button) = prefixed("Mouse:") {
+        let button = match button.to_uppercase().as_str() {
+            "LEFT" => MouseButton::Left,
+            "RIGHT" => MouseButton::Right,
+            "MIDDLE" => MouseButton::Middle,
+            "BACK" => MouseButton::Back,
+            "FORWARD" => MouseButton::Forward,
+            other => MouseButton::Other(
+                other
+                    .parse()
+                    .map_err(|_| format!("unknown mouse button \"{button}\""))?,
+            ),
+        };
+
+        return Ok(Trigger::Mouse(button));
+    }
+
+    if let Some(direction) = prefixed("Wheel:") {
+        let direction = match direction.to_uppercase().as_str() {
+            "UP" => WheelDirection::Up,
+            "DOWN" => WheelDirection::Down,
+            _ => return Err(format!("unknown wheel direction \"{direction}\"")),
+        };
+
+        return Ok(Trigger::Wheel(direction));
+    }
+
+    let key = part.to_uppercase();
+    let key = match key.as_str() {
+        "ESCAPE" => "ESC".to_string(),
+        "RETURN" => "ENTER".to_string(),
+        "DEL" => "DELETE".to_string(),
+        _ => key,
+    };
+
+    if key.chars().count() == 1 || NAMED_KEYS.iter().any(|(name, _, _)| *name == key) {
+        Ok(Trigger::Key(key))
+    } else {
+        Err(format!("unknown key \"{part}\""))
+    }
+}
+
+/// name, logical key, physical key
+const NAMED_KEYS: &[(&str, NamedKey, KeyCode)] = &[
+    ("ESC", NamedKey::Escape, KeyCode::Escape),
+    ("SPACE", NamedKey::Space, KeyCode::Space),
+    ("ENTER", NamedKey::Enter, KeyCode::Enter),
+    ("TAB", NamedKey::Tab, KeyCode::Tab),
+    ("BACKSPACE", NamedKey::Backspace, KeyCode::Backspace),
+    ("DELETE", NamedKey::Delete, KeyCode::Delete),
+    ("UP", NamedKey::ArrowUp, KeyCode::ArrowUp),
+    ("DOWN", NamedKey::ArrowDown, KeyCode::ArrowDown),
+    ("LEFT", NamedKey::ArrowLeft, KeyCode::ArrowLeft),
+    ("RIGHT", NamedKey::ArrowRight, KeyCode::ArrowRight),
+    ("F1", NamedKey::F1, KeyCode::F1),
+    ("F2", NamedKey::F2, KeyCode::F2),
+    ("F3", NamedKey::F3, KeyCode::F3),
+    ("F4", NamedKey::F4, KeyCode::F4),
+    ("F5", NamedKey::F5, KeyCode::F5),
+    ("F6", NamedKey::F6, KeyCode::F6),
+    ("F7", NamedKey::F7, KeyCode::F7),
+    ("F8", NamedKey::F8, KeyCode::F8),
+    ("F9", NamedKey::F9, KeyCode::F9),
+    ("F10", NamedKey::F10, KeyCode::F10),
+    ("F11", NamedKey::F11, KeyCode::F11),
+    ("F12", NamedKey::F12, KeyCode::F12),
+];
+
+/// Name keyboard bindings are matched against, characters are uppercased
+pub fn key_name(key: &Key) -> Option<String> {
+    match key {
+        Key::Character(ch) => Some(ch.to_uppercase()),
+        Key::Named(named) => NAMED_KEYS
+            .iter()
+            .find(|(_, n, _)| n == named)
+            .map(|(name, _, _)| name.to_string()),
+        _ => None,
+    }
+}
+
+/// Physical key for a key name, only letters, digits and the named keys are supported
+pub fn parse_key_code(key: &str) -> Option<KeyCode> {
+    if let Some((_, _, code)) = NAMED_KEYS.iter().find(|(name, _, _)| *name == key) {
+        return Some(*code);
+    }
+
+    const LETTERS: [KeyCode; 26] = [
+        KeyCode::KeyA,
+        KeyCode::KeyB,
+        KeyCode::KeyC,
+        KeyCode::KeyD,
+        KeyCode::KeyE,
+        KeyCode::KeyF,
+        KeyCode::KeyG,
+        KeyCode::KeyH,
+        KeyCode::KeyI,
+        KeyCode::KeyJ,
+        KeyCode::KeyK,
+        KeyCode::KeyL,
+        KeyCode::KeyM,
+        KeyCode::KeyN,
+        KeyCode::KeyO,
+        KeyCode::KeyP,
+        KeyCode::KeyQ,
+        KeyCode::KeyR,
+        KeyCode::KeyS,
+        KeyCode::KeyT,
+        KeyCode::KeyU,
+        KeyCode::KeyV,
+        KeyCode::KeyW,
+        KeyCode::KeyX,
+        KeyCode::KeyY,
+        KeyCode::KeyZ,
+    ];
+    const DIGITS: [KeyCode; 10] = [
+        KeyCode::Digit0,
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ 'A'..='Z'), None) => Some(LETTERS[c as usize - 'A' as usize]),
+        (Some(c @ '0'..='9'), None) => Some(DIGITS[c as usize - '0' as usize]),
+        _ => None,
+    }
+}
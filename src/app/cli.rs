@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use super::config;
+
+const WHITEBOARD_COLOR: u32 = 0xffffffff;
+
+pub const USAGE: &str = "\
+usage: draw [options]
+
+options:
+  --draw                    start in draw mode instead of minimized
+  --color <#RRGGBB[AA]>     brush color to start with
+  --size <n>                brush size to start with, 1 to 10
+  --config <path>           keybinding config file to use
+  --monitor <n>             which monitor to cover, 0 is the first one
+  --whiteboard              draw on a white background instead of the desktop
+  --background <color>      draw on a background of any color
+  --open <path>             session to open instead of the last autosave
+  --export <session> <png>  render a saved session to a png and exit
+  --check-bindings          report problems with the keybindings and exit
+  --version                 print the version and exit
+  --help                    print this and exit";
+
+/// Everything that can be set from the command line
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub draw_mode: bool,
+    pub color: Option<u32>,
+    pub brush_size: Option<u32>,
+    pub config: Option<PathBuf>,
+    pub monitor: Option<usize>,
+    /// Opaque color behind the drawing, None shows whatever is under the window
+    pub background: Option<u32>,
+    /// Session file to start with, None picks up the autosave
+    pub open: Option<PathBuf>,
+    /// `--export`, the session to render and the png to write it to
+    pub export: Option<(PathBuf, PathBuf)>,
+
+    pub check_bindings: bool,
+    pub version: bool,
+    pub help: bool,
+}
+
+impl Options {
+    /// Parses the arguments after the program name, values can be given as `--size 4` or `--size=4`
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{flag} needs a value"))
+            };
+
+            match flag.as_str() {
+                "--draw" => options.draw_mode = true,
+                "--color" => options.color = Some(parse_color(&value()?)?),
+                "--size" => {
+                    let size = value()?;
+                    options.brush_size = match size.parse() {
+                        Ok(n @ 1..=10) => Some(n),
+                        _ => return Err(format!("brush size \"{size}\" isnt between 1 and 10")),
+                    };
+                }
+                "--config" => options.config = Some(PathBuf::from(value()?)),
+                "--monitor" => {
+                    let monitor = value()?;
+                    options.monitor = Some(
+                        monitor
+                            .parse()
+                            .map_err(|_| format!("\"{monitor}\" isnt a monitor number"))?,
+                    );
+                }
+                "--whiteboard" => options.background = Some(WHITEBOARD_COLOR),
+                "--background" => options.background = Some(parse_color(&value()?)?),
+                "--open" => options.open = Some(PathBuf::from(value()?)),
+                "--export" => {
+                    let session = PathBuf::from(value()?);
+                    let png = args.next().ok_or("--export needs a session and a png")?;
+                    options.export = Some((session, PathBuf::from(png)));
+                }
+                "--check-bindings" => options.check_bindings = true,
+                "--version" | "-V" => options.version = true,
+                "--help" | "-h" => options.help = true,
+                _ => return Err(format!("unknown option \"{flag}\"")),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// The config file to read bindings from, `--config` or the one in the platform config dir
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(config::default_config_path)
+    }
+}
+
+/// `#RRGGBB` or `#RRGGBBAA`, the `#` is optional. Returns 0xAARRGGBB
+pub fn parse_color(s: &str) -> Result<u32, String> {
+    let hex = s.trim().trim_start_matches('#');
+
+    let value = match hex.len() {
+        6 | 8 => u32::from_str_radix(hex, 16).ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("\"{s}\" isnt a color, expected #RRGGBB or #RRGGBBAA"))?;
+
+    Ok(match hex.len() {
+        6 => 0xff000000 | value,
+        _ => value.rotate_right(8),
+    })
+}
@@ -0,0 +1,165 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::actions::Actions;
+use super::bindings::{Bindings, TriggerEvents};
+use crate::platform;
+use winit::event::ElementState;
+
+/// What the user can put in `config.toml`
+///
+/// ```toml
+/// # how long a key sequence waits for its next key, in milliseconds
+/// sequence_timeout = 1000
+///
+/// [[binding]]
+/// trigger = "Ctrl+Alt+D"
+/// action = "ToggleDrawMode"
+/// global = true
+///
+/// [[binding]]
+/// trigger = "Mouse:Left"
+/// action = "SetDrawing"
+/// when = "Hold"
+///
+/// [[binding]]
+/// trigger = "Space"
+/// action = "ToggleEraser"
+/// when = "DoubleTap"
+///
+/// [[binding]]
+/// trigger = "Ctrl+K L"
+/// action = "SelectTool(Line)"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    pub sequence_timeout: Option<u64>,
+
+    #[serde(default)]
+    pub binding: Vec<BindingEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BindingEntry {
+    pub trigger: String,
+    pub action: String,
+
+    /// Works even while the window isnt focused, keyboard only
+    #[serde(default)]
+    pub global: bool,
+
+    /// `Press`, `Release`, `Toggle`, `Hold`, `DoubleTap` or `LongPress`, defaults to what suits the action
+    pub when: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Index of the `[[binding]]` entry, None when the whole file is broken
+    pub entry: Option<usize>,
+    pub message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.entry {
+            Some(i) => write!(f, "binding #{}: {}", i + 1, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    Some(platform::config_dir()?.join("draw").join("config.toml"))
+}
+
+/// Loads the bindings from `path`, a missing file just means the defaults
+pub fn load_bindings(path: &Path) -> (Bindings, Vec<ConfigError>) {
+    match std::fs::read_to_string(path) {
+        Ok(src) => parse_bindings(&src),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Bindings::default(), Vec::new()),
+        Err(e) => (
+            Bindings::default(),
+            vec![ConfigError {
+                entry: None,
+                message: format!("couldnt read {}: {e}", path.display()),
+            }],
+        ),
+    }
+}
+
+/// Defaults with every valid entry applied, actions the config binds lose their default bindings.
+/// Invalid entries are skipped and reported, they dont take the defaults away
+pub fn parse_bindings(src: &str) -> (Bindings, Vec<ConfigError>) {
+    let mut bindings = Bindings::default();
+    let mut errors = Vec::new();
+
+    let file: ConfigFile = match toml::from_str(src) {
+        Ok(file) => file,
+        Err(e) => {
+            errors.push(ConfigError {
+                entry: None,
+                message: e.to_string(),
+            });
+            return (bindings, errors);
+        }
+    };
+
+    if let Some(ms) = file.sequence_timeout {
+        bindings.sequence_timeout = Duration::from_millis(ms);
+    }
+
+    let mut parsed = Vec::new();
+    for (i, entry) in file.binding.iter().enumerate() {
+        match parse_entry(entry) {
+            Ok((action, conditions)) => parsed.push((i, entry, action, conditions)),
+            Err(message) => errors.push(ConfigError {
+                entry: Some(i),
+                message: format!("\"{}\": {message}", entry.trigger),
+            }),
+        }
+    }
+
+    // entries go into their own set first, so a default only goes once something valid replaces it
+    let mut replacements = Bindings::empty();
+    for (i, entry, action, conditions) in parsed {
+        if let Err(message) = replacements.add(&entry.trigger, action, conditions, entry.global) {
+            errors.push(ConfigError {
+                entry: Some(i),
+                message: format!("\"{}\": {message}", entry.trigger),
+            });
+        }
+    }
+
+    bindings.replace(replacements);
+
+    (bindings, errors)
+}
+
+fn parse_entry(entry: &BindingEntry) -> Result<(Actions, TriggerEvents), String> {
+    let action: Actions = entry.action.parse()?;
+
+    let conditions = match entry.when.as_deref().map(str::to_uppercase).as_deref() {
+        None => action.default_trigger(),
+        Some("PRESS") => TriggerEvents::OneTime(ElementState::Pressed),
+        Some("RELEASE") => TriggerEvents::OneTime(ElementState::Released),
+        Some("TOGGLE") => TriggerEvents::Toggle,
+        Some("HOLD") => TriggerEvents::Hold,
+        Some("DOUBLETAP") => TriggerEvents::DoubleTap,
+        Some("LONGPRESS") => TriggerEvents::LongPress,
+        Some(_) => {
+            return Err(format!(
+                "unknown \"when\" value \"{}\"",
+                entry.when.as_deref().unwrap_or_default()
+            ))
+        }
+    };
+
+    Ok((action, conditions))
+}
@@ -1,7 +1,9 @@
 pub mod actions;
 pub mod apphandler;
 pub mod bindings;
+pub mod cli;
+pub mod config;
 pub mod program;
+pub mod settings;
+pub mod watcher;
 pub mod windowstate;
-
-pub use program as app;
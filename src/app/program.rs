@@ -1,17 +1,26 @@
+use crate::modules::canvas::NUDGE_STEP;
 use crate::modules::dmodifiers::DModifiers;
+use crate::modules::export;
+use crate::modules::layer::OPACITY_STEP;
+use crate::modules::session::Session;
+use crate::platform::{self, Tray};
 
+use super::actions::Actions;
 use super::apphandler::UserEvent;
-use super::bindings::{DEVICE_BINDINGS, KEYBOARD_BINDINGS};
+use super::bindings::{
+    process_event, process_key, tick_bindings, Bindings, KeyState, WheelDirection,
+};
+use super::cli::Options;
+use super::config;
+use super::settings::Settings;
+use super::watcher::FileWatcher;
 use super::windowstate::WindowState;
-use super::{actions::Actions, bindings::MOUSE_BINDINGS};
 
 use softbuffer::Context;
-use std::{collections::HashMap, error::Error};
-use trayicon::TrayIcon;
+use std::{collections::HashMap, error::Error, path::Path};
 use wgpu::rwh::{DisplayHandle, HasDisplayHandle};
 use winit::event::ElementState;
 use winit::keyboard::KeyCode;
-use winit::platform::windows::WindowExtWindows;
 use winit::window::Fullscreen;
 use winit::{
     event::MouseButton,
@@ -31,13 +40,35 @@ pub struct Application {
 
     pub dmods: DModifiers,
 
-    pub keymap: HashMap<KeyCode, bool>,
+    pub bindings: Bindings,
+    /// Held keys and unfinished key sequences, focused and global ones are separate
+    pub keys: KeyState<String>,
+    pub device_keys: KeyState<KeyCode>,
+
+    /// What was asked for on the command line
+    pub options: Options,
+
+    /// Last used brush and window preferences, saved whenever they change
+    pub settings: Settings,
+    saved_settings: Settings,
 
-    pub tray: TrayIcon<UserEvent>,
+    /// Config and settings files, reloaded when theyre edited
+    config_watcher: Option<FileWatcher>,
+    settings_watcher: Option<FileWatcher>,
+
+    pub tray: Tray,
+    /// Whether the hotkeys still work while the window isnt focused
+    global_keys: bool,
 }
 
 impl Application {
-    pub fn new<T>(event_loop: &EventLoop<T>, tray: TrayIcon<UserEvent>) -> Self {
+    pub fn new<T>(
+        event_loop: &EventLoop<T>,
+        tray: Tray,
+        bindings: Bindings,
+        settings: Settings,
+        options: Options,
+    ) -> Self {
         // we HAVE to drop the context right before the event loop stops, or else we will fucking LEAK memory !
         let context = Some(
             Context::new(unsafe {
@@ -53,12 +84,23 @@ impl Application {
         Self {
             context,
             tray,
+            global_keys: platform::global_keys(event_loop),
 
             windows: Default::default(),
 
             dmods: Default::default(),
 
-            keymap: Default::default(),
+            bindings,
+            keys: Default::default(),
+            device_keys: Default::default(),
+
+            config_watcher: options.config_path().map(FileWatcher::new),
+            settings_watcher: Settings::default_path().map(FileWatcher::new),
+
+            options,
+
+            saved_settings: settings.clone(),
+            settings,
         }
     }
 
@@ -66,40 +108,97 @@ impl Application {
         &mut self,
         event_loop: &ActiveEventLoop,
     ) -> Result<WindowId, Box<dyn Error>> {
+        let monitor = match self.options.monitor.or(self.settings.window.monitor) {
+            None => None,
+            Some(n) => {
+                let monitor = event_loop.available_monitors().nth(n);
+                if monitor.is_none() {
+                    eprintln!("theres no monitor {n}, using the current one");
+                }
+
+                monitor
+            }
+        };
+
         let window_attributes = Window::default_attributes()
             .with_title("test_window")
-            .with_fullscreen(Some(Fullscreen::Borderless(None)))
+            .with_fullscreen(Some(Fullscreen::Borderless(monitor.clone())))
             // .with_visible(false)
-            .with_window_level(WindowLevel::AlwaysOnTop)
             .with_decorations(false)
             .with_transparent(true);
 
-        let window = event_loop.create_window(window_attributes)?;
-        window.set_skip_taskbar(true);
+        let window = event_loop.create_window(platform::window_attributes(window_attributes))?;
 
-        let window_state = WindowState::new(self, window)?;
+        let mut window_state = WindowState::new(self, window)?;
         let window_id = window_state.window.id();
 
+        window_state.monitor = monitor;
+
+        let canvas = &mut window_state.canvas;
+        canvas.background = self.settings.window.background;
+        canvas.brush_color = self.settings.brush_color;
+        canvas.brush_size = self.settings.brush_size.clamp(1, 10);
+        canvas.tool = self.settings.tool;
+        canvas.smoother.settings = self.settings.smoothing;
+        canvas.history_limit = self.settings.history_limit();
+
+        // pick up where the last session left off, or whichever one was asked for
+        let session = match &self.options.open {
+            Some(path) => Some(path.clone()),
+            None => Session::autosave_path().filter(|path| path.exists()),
+        };
+        if let Some(path) = session {
+            if let Err(e) = Session::load(&path).and_then(|session| canvas.restore(session)) {
+                eprintln!("couldnt open {}: {e}", path.display());
+                canvas.show_notice(format!("couldnt open {}: {e}", file_name(&path)), true);
+            }
+        }
+
+        // command line options win over whatever was used last time
+        if let Some(background) = self.options.background {
+            canvas.background = Some(background);
+        }
+        if let Some(color) = self.options.color {
+            canvas.brush_color = color;
+        }
+        if let Some(size) = self.options.brush_size {
+            canvas.brush_size = size.clamp(1, 10);
+        }
+
         self.windows.insert(window_id, window_state);
 
         Ok(window_id)
     }
 
+    /// Hiding the window is only ok while theres a way to bring it back,
+    /// otherwise it just gets minimized to the taskbar
+    pub fn can_hide(&self) -> bool {
+        self.global_keys || self.tray.is_shown()
+    }
+
+    /// Closes a window, its drawing is kept in the autosave
+    pub fn close_window(&mut self, window_id: WindowId) {
+        if let Some(window) = self.windows.remove(&window_id) {
+            window.autosave();
+        }
+    }
+
     pub fn handle_action(
         &mut self,
         _event_loop: &ActiveEventLoop,
         window_id: WindowId,
         action: Actions,
     ) {
+        let hide = self.can_hide();
         let window = self.windows.get_mut(&window_id).unwrap();
 
         match action {
             Actions::CloseWindow => {
-                let _ = self.windows.remove(&window_id);
+                self.close_window(window_id);
             }
 
             Actions::Minimize => {
-                window.minimize();
+                window.minimize(hide);
             }
 
             Actions::ToggleMaximize => {}
@@ -115,7 +214,7 @@ impl Application {
             // yeah these feel repetitive but i think it could be useful for something at somepoint
             Actions::ToggleDrawMode => match window.draw_mode {
                 true => {
-                    window.exit_draw_mode();
+                    window.exit_draw_mode(hide);
                 }
                 false => {
                     window.enter_draw_mode();
@@ -130,7 +229,7 @@ impl Application {
 
             Actions::ExitDrawMode => {
                 if window.draw_mode {
-                    window.exit_draw_mode();
+                    window.exit_draw_mode(hide);
                 }
             }
 
@@ -142,45 +241,456 @@ impl Application {
                 window.canvas.undo();
             }
 
+            Actions::NextBranch | Actions::PreviousBranch => {
+                let step = match action {
+                    Actions::NextBranch => 1,
+                    _ => -1,
+                };
+
+                match window.canvas.switch_branch(step) {
+                    Some((branch, count)) => window
+                        .canvas
+                        .show_notice(format!("branch {branch} of {count}"), false),
+                    None => window
+                        .canvas
+                        .show_notice("theres no other branch from here", false),
+                }
+            }
+
+            Actions::ToggleBrushEdge => {
+                window.canvas.toggle_brush_edge();
+            }
+
+            Actions::ToggleEraser => {
+                window.canvas.toggle_eraser();
+            }
+
+            Actions::ToggleEraseMode => {
+                window.canvas.toggle_erase_mode();
+            }
+
+            Actions::SelectTool(tool) => {
+                window.canvas.tool = tool;
+            }
+
+            Actions::GrowBrush => {
+                window.canvas.change_brush_size(1);
+            }
+
+            Actions::ShrinkBrush => {
+                window.canvas.change_brush_size(-1);
+            }
+
+            Actions::NextColor => {
+                if let Some(color) = self.settings.next_color(window.canvas.brush_color) {
+                    window.canvas.brush_color = color;
+                }
+            }
+
+            Actions::SaveImage | Actions::SaveSvg => {
+                let saved = match self.settings.export_dir() {
+                    None => Err("theres nowhere to save images to".into()),
+                    Some(dir) if action == Actions::SaveSvg => {
+                        let path = export::timestamped_path(&dir, "svg");
+                        window.canvas.save_svg(&path).map(|_| path)
+                    }
+                    Some(dir) => {
+                        let path = export::timestamped_path(&dir, "png");
+                        window.canvas.save_png(&path).map(|_| path)
+                    }
+                };
+
+                match saved {
+                    Ok(path) => window
+                        .canvas
+                        .show_notice(format!("saved {}", path.display()), false),
+                    Err(e) => window
+                        .canvas
+                        .show_notice(format!("couldnt save image: {e}"), true),
+                }
+            }
+
+            Actions::SaveSession => {
+                let saved = match self.settings.export_dir() {
+                    None => Err("theres nowhere to save sessions to".into()),
+                    Some(dir) => {
+                        let path = export::timestamped_path(&dir, "draw");
+                        window.canvas.session().save(&path).map(|_| path)
+                    }
+                };
+
+                match saved {
+                    Ok(path) => window
+                        .canvas
+                        .show_notice(format!("saved {}", path.display()), false),
+                    Err(e) => window
+                        .canvas
+                        .show_notice(format!("couldnt save session: {e}"), true),
+                }
+            }
+
+            Actions::CopyImage | Actions::CopyInk => {
+                if action == Actions::CopyInk {
+                    window.canvas.copy_strokes();
+                }
+
+                match window.canvas.copy_to_clipboard(action == Actions::CopyInk) {
+                    Ok(_) => window.canvas.show_notice("copied to the clipboard", false),
+                    Err(e) => window
+                        .canvas
+                        .show_notice(format!("couldnt copy: {e}"), true),
+                }
+            }
+
+            Actions::PasteInk => {
+                if !window.canvas.paste_copied() {
+                    window
+                        .canvas
+                        .show_notice("theres nothing copied to paste", false);
+                }
+            }
+
+            Actions::ClearCanvas => {
+                window.canvas.clear();
+            }
+
+            Actions::FillCanvas => {
+                window.canvas.fill(window.canvas.brush_color);
+            }
+
+            Actions::AddLayer => {
+                window.canvas.add_layer();
+                window
+                    .canvas
+                    .show_notice(window.canvas.layer_status(), false);
+            }
+
+            Actions::NextLayer | Actions::PreviousLayer => {
+                let index = match action {
+                    Actions::NextLayer => window.canvas.active_layer + 1,
+                    _ => window.canvas.active_layer.wrapping_sub(1),
+                };
+
+                if window.canvas.select_layer(index) {
+                    window
+                        .canvas
+                        .show_notice(window.canvas.layer_status(), false);
+                }
+            }
+
+            Actions::MergeLayerDown => match window.canvas.merge_layer_down() {
+                Ok(_) => window
+                    .canvas
+                    .show_notice(window.canvas.layer_status(), false),
+                Err(e) => window
+                    .canvas
+                    .show_notice(format!("couldnt merge: {e}"), true),
+            },
+
+            Actions::ToggleLayerVisibility => {
+                let layer = window.canvas.layer_mut();
+                layer.visible = !layer.visible;
+                window
+                    .canvas
+                    .show_notice(window.canvas.layer_status(), false);
+            }
+
+            Actions::ToggleLayerLock => {
+                let layer = window.canvas.layer_mut();
+                layer.locked = !layer.locked;
+                window
+                    .canvas
+                    .show_notice(window.canvas.layer_status(), false);
+            }
+
+            Actions::RaiseLayerOpacity | Actions::LowerLayerOpacity => {
+                let step = match action {
+                    Actions::RaiseLayerOpacity => OPACITY_STEP,
+                    _ => -OPACITY_STEP,
+                };
+
+                window.canvas.change_layer_opacity(step);
+                window
+                    .canvas
+                    .show_notice(window.canvas.layer_status(), false);
+            }
+
+            Actions::MoveLayerLeft
+            | Actions::MoveLayerRight
+            | Actions::MoveLayerUp
+            | Actions::MoveLayerDown => {
+                let (dx, dy) = match action {
+                    Actions::MoveLayerLeft => (-NUDGE_STEP, 0.0),
+                    Actions::MoveLayerRight => (NUDGE_STEP, 0.0),
+                    Actions::MoveLayerUp => (0.0, -NUDGE_STEP),
+                    _ => (0.0, NUDGE_STEP),
+                };
+
+                window.canvas.nudge(dx, dy);
+            }
+
+            Actions::SelectStroke => {
+                let picked = (window.cursor_pos.current)
+                    .and_then(|location| window.canvas.select_at(location));
+
+                if picked.is_some() {
+                    window
+                        .canvas
+                        .show_notice("picked a stroke to move or recolor", false);
+                }
+            }
+
+            Actions::RecolorStroke => {
+                let color = window.canvas.brush_color;
+                window.canvas.recolor_selected(color);
+            }
+
+            Actions::MoveStrokeLeft
+            | Actions::MoveStrokeRight
+            | Actions::MoveStrokeUp
+            | Actions::MoveStrokeDown => {
+                let (dx, dy) = match action {
+                    Actions::MoveStrokeLeft => (-NUDGE_STEP, 0.0),
+                    Actions::MoveStrokeRight => (NUDGE_STEP, 0.0),
+                    Actions::MoveStrokeUp => (0.0, -NUDGE_STEP),
+                    _ => (0.0, NUDGE_STEP),
+                };
+
+                window.canvas.move_selected(dx, dy);
+            }
+
             Actions::SetDrawing => {
                 window.invert_drawing();
             }
+
+            Actions::SetErasing => {
+                window.invert_erasing();
+            }
+        }
+
+        self.sync_settings(window_id);
+    }
+
+    /// Picks up brush changes from the window and saves them if anything is different
+    pub fn sync_settings(&mut self, window_id: WindowId) {
+        if let Some(window) = self.windows.get(&window_id) {
+            let canvas = &window.canvas;
+
+            self.settings.brush_color = canvas.brush_color;
+            self.settings.brush_size = canvas.brush_size;
+            self.settings.tool = canvas.tool;
+        }
+
+        if self.settings != self.saved_settings {
+            self.save_settings();
+        }
+    }
+
+    pub fn save_settings(&mut self) {
+        let Some(path) = Settings::default_path() else {
+            return;
+        };
+
+        match self.settings.save(&path) {
+            Ok(()) => self.saved_settings = self.settings.clone(),
+            Err(e) => eprintln!("couldnt save settings to {}: {e}", path.display()),
         }
     }
 
     pub fn process_mouse_binding(
+        &mut self,
         button: MouseButton,
         mods: &ModifiersState,
         state: ElementState,
     ) -> Option<Actions> {
-        MOUSE_BINDINGS.iter().find_map(|binding| {
+        process_event(&mut self.bindings.mouse, &[], &button, mods, &state)
+    }
+
+    pub fn process_wheel_binding(
+        &self,
+        direction: WheelDirection,
+        mods: &ModifiersState,
+    ) -> Option<Actions> {
+        self.bindings.wheel.iter().find_map(|binding| {
             binding
-                .is_triggered_by(&button, mods, &state)
+                .is_triggered_by(&[], &direction, mods, &ElementState::Pressed)
                 .then_some(binding.action)
         })
     }
 
     pub fn process_keyboard_binding(
+        &mut self,
         key: &str,
         mods: &ModifiersState,
         state: ElementState,
+        repeat: bool,
     ) -> Option<Actions> {
-        KEYBOARD_BINDINGS.iter().find_map(|binding| {
-            binding
-                .is_triggered_by(&key, mods, &state)
-                .then_some(binding.action)
-        })
+        let action = process_key(
+            &mut self.bindings.keyboard,
+            &mut self.keys,
+            self.bindings.sequence_timeout,
+            key,
+            mods,
+            &state,
+            repeat,
+        );
+
+        self.update_sequence_hint();
+        action
     }
 
     pub fn process_device_binding(
+        &mut self,
         key: KeyCode,
         mods: DModifiers,
         state: ElementState,
     ) -> Option<Actions> {
-        DEVICE_BINDINGS.iter().find_map(|binding| {
-            binding
-                .is_triggered_by(&key, &mods.into(), &state)
-                .then_some(binding.action)
-        })
+        let action = process_key(
+            &mut self.bindings.device,
+            &mut self.device_keys,
+            self.bindings.sequence_timeout,
+            &key,
+            &mods.into(),
+            &state,
+            false,
+        );
+
+        self.update_sequence_hint();
+        action
     }
+
+    /// Drops sequences that timed out and shows whatever is still pending
+    pub fn update_sequence_hint(&mut self) {
+        let timeout = self.bindings.sequence_timeout;
+
+        if self.keys.is_expired(timeout) {
+            self.keys.clear_pending();
+        }
+        if self.device_keys.is_expired(timeout) {
+            self.device_keys.clear_pending();
+        }
+
+        let hint = self.keys.hint().or(self.device_keys.hint());
+
+        self.windows
+            .values_mut()
+            .for_each(|window| window.canvas.hint = hint.clone());
+    }
+
+    /// Fires long presses, they dont have an event of their own
+    pub fn tick_bindings(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(window_id) = self.windows.keys().next().copied() else {
+            return;
+        };
+
+        let actions: Vec<Actions> = [
+            tick_bindings(&mut self.bindings.mouse),
+            tick_bindings(&mut self.bindings.keyboard),
+            tick_bindings(&mut self.bindings.device),
+        ]
+        .concat();
+
+        for action in actions {
+            self.handle_action(event_loop, window_id, action);
+        }
+    }
+
+    /// Shows `text` on top of every window for a few seconds
+    pub fn notify(&mut self, text: &str, error: bool) {
+        self.windows
+            .values_mut()
+            .for_each(|window| window.canvas.show_notice(text, error));
+    }
+
+    /// Reloads the config or settings file if either was edited
+    pub fn check_config_files(&mut self) {
+        if self.config_watcher.as_mut().is_some_and(|w| w.changed()) {
+            self.reload_bindings();
+        }
+
+        if self.settings_watcher.as_mut().is_some_and(|w| w.changed()) {
+            self.reload_settings();
+        }
+    }
+
+    fn reload_bindings(&mut self) {
+        let Some(path) = self.config_watcher.as_ref().map(|w| w.path.clone()) else {
+            return;
+        };
+        let name = file_name(&path);
+
+        let (bindings, errors) = config::load_bindings(&path);
+
+        for e in &errors {
+            eprintln!("{}: {e}", path.display());
+        }
+
+        // a file that doesnt parse at all would mean going back to the defaults, keep what works instead
+        if errors.iter().any(|e| e.entry.is_none()) {
+            self.notify(&format!("{name}: {}", first_line(&errors[0])), true);
+            return;
+        }
+
+        self.bindings = bindings;
+        self.keys.reset();
+        self.device_keys.reset();
+
+        match errors.as_slice() {
+            [] => self.notify(&format!("reloaded {name}"), false),
+            [e] => self.notify(&format!("{name}: {}", first_line(e)), true),
+            [e, rest @ ..] => self.notify(
+                &format!("{name}: {} (and {} more)", first_line(e), rest.len()),
+                true,
+            ),
+        }
+    }
+
+    fn reload_settings(&mut self) {
+        let Some(path) = self.settings_watcher.as_ref().map(|w| w.path.clone()) else {
+            return;
+        };
+        let name = file_name(&path);
+
+        let settings = match Settings::load(&path) {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                self.notify(&format!("{name}: {}", first_line(&e)), true);
+                return;
+            }
+        };
+
+        // most of the time its just us saving
+        if settings == self.settings {
+            return;
+        }
+
+        self.settings = settings.clone();
+        self.saved_settings = settings;
+
+        let background = self.options.background.or(self.settings.window.background);
+        for window in self.windows.values_mut() {
+            window.canvas.brush_color = self.settings.brush_color;
+            window.canvas.brush_size = self.settings.brush_size.clamp(1, 10);
+            window.canvas.tool = self.settings.tool;
+            window.canvas.smoother.settings = self.settings.smoothing;
+            window.canvas.background = background;
+            window.canvas.history_limit = self.settings.history_limit();
+            window.canvas.trim_history();
+        }
+
+        self.notify(&format!("reloaded {name}"), false);
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into(),
+    )
+}
+
+/// Parse errors can span several lines, only the first fits on screen
+fn first_line(e: &impl std::fmt::Display) -> String {
+    e.to_string().lines().next().unwrap_or_default().to_string()
 }
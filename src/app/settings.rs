@@ -0,0 +1,201 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::modules::canvas::{BRUSH_SIZE, DEFAULT_BRUSH_COLOR, HISTORY_LIMIT};
+use crate::modules::smoothing::Smoothing;
+use crate::modules::stroke::Tool;
+use crate::platform;
+
+/// Things the app remembers between launches, unlike `config.toml` this one gets written by the app
+///
+/// ```toml
+/// brush_color = "#ffccaaff"
+/// brush_size = 2
+/// tool = "Pen"
+/// palette = ["#ffccaaff", "#ff5555ff", "#55ff55ff"]
+/// export_dir = "~/Pictures/draw"
+/// history_limit_mb = 256
+///
+/// [smoothing]
+/// spline = true
+/// stabilizer = 8.0
+///
+/// [window]
+/// monitor = 1
+/// background = "#ffffffff"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    #[serde(with = "color")]
+    pub brush_color: u32,
+    pub brush_size: u32,
+    pub tool: Tool,
+    /// Colors `NextColor` goes through
+    #[serde(with = "colors")]
+    pub palette: Vec<u32>,
+
+    /// Where saved images go, defaults to a `draw` folder in the pictures dir. `~` is the home dir
+    pub export_dir: Option<PathBuf>,
+
+    /// Memory the undo history can use before the oldest steps are forgotten
+    pub history_limit_mb: usize,
+
+    /// How cursor samples get smoothed before theyre drawn, `stabilizer` is the lazy brush
+    /// radius in pixels and 0 turns it off
+    pub smoothing: Smoothing,
+
+    pub window: WindowSettings,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    /// Monitor to cover, None for whichever the window opens on
+    pub monitor: Option<usize>,
+    /// Opaque color behind the drawing, None leaves the window see through
+    #[serde(with = "optional_color")]
+    pub background: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            brush_color: DEFAULT_BRUSH_COLOR,
+            brush_size: BRUSH_SIZE,
+            tool: Tool::Pen,
+            palette: vec![
+                DEFAULT_BRUSH_COLOR,
+                0xffff5555,
+                0xff55ff55,
+                0xff5599ff,
+                0xffffee55,
+                0xffffffff,
+                0xff181818,
+            ],
+
+            export_dir: None,
+
+            history_limit_mb: HISTORY_LIMIT / 1024 / 1024,
+
+            smoothing: Smoothing::default(),
+
+            window: WindowSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(platform::config_dir()?.join("draw").join("settings.toml"))
+    }
+
+    /// A missing file just means the defaults
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(src) => Ok(toml::from_str(&src)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // write next to it first so a crash halfway through doesnt leave a broken file behind
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, toml::to_string(self)?)?;
+        std::fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    pub fn export_dir(&self) -> Option<PathBuf> {
+        let Some(dir) = &self.export_dir else {
+            return Some(platform::pictures_dir()?.join("draw"));
+        };
+
+        match dir.strip_prefix("~") {
+            Ok(rest) => Some(platform::home_dir()?.join(rest)),
+            Err(_) => Some(dir.clone()),
+        }
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.history_limit_mb.saturating_mul(1024 * 1024)
+    }
+
+    /// The palette color after `current`, the first one if `current` isnt in the palette
+    pub fn next_color(&self, current: u32) -> Option<u32> {
+        let next = self
+            .palette
+            .iter()
+            .position(|c| *c == current)
+            .map_or(0, |i| i + 1);
+
+        self.palette.get(next).or(self.palette.first()).copied()
+    }
+}
+
+/// Colors are stored as `#RRGGBBAA` strings instead of numbers nobody can read
+pub mod color {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::app::cli::parse_color;
+
+    pub fn to_string(color: u32) -> String {
+        format!("#{:08x}", color.rotate_left(8))
+    }
+
+    pub fn serialize<S: Serializer>(color: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_string(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        parse_color(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+pub mod colors {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::app::cli::parse_color;
+
+    pub fn serialize<S: Serializer>(colors: &[u32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(colors.iter().map(|c| super::color::to_string(*c)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u32>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|c| parse_color(c).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+pub mod optional_color {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::app::cli::parse_color;
+
+    pub fn serialize<S: Serializer>(color: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        match color {
+            Some(c) => serializer.serialize_some(&super::color::to_string(*c)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|c| parse_color(&c).map_err(D::Error::custom))
+            .transpose()
+    }
+}
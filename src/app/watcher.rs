@@ -0,0 +1,47 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the file is looked at, checking every frame would be a waste
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when a file gets written by comparing its modified time
+#[derive(Debug)]
+pub struct FileWatcher {
+    pub path: PathBuf,
+
+    /// None while the file doesnt exist
+    modified: Option<SystemTime>,
+    checked_at: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            modified: modified(&path),
+            path,
+            checked_at: Instant::now(),
+        }
+    }
+
+    /// Whether the file was written, created or deleted since the last time this returned true
+    pub fn changed(&mut self) -> bool {
+        if self.checked_at.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.checked_at = Instant::now();
+
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
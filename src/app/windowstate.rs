@@ -1,13 +1,19 @@
 use std::{error::Error, num::NonZeroU32, sync::Arc};
 
 use super::program::Application;
-use crate::modules::canvas::{Canvas, PixelChange};
+use crate::modules::canvas::Canvas;
+use crate::modules::history::PixelChange;
+use crate::modules::pen::PenState;
+use crate::modules::session::Session;
+use crate::platform;
 
 use softbuffer::Surface;
 use wgpu::rwh::DisplayHandle;
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
+    event::{Touch, TouchPhase},
     keyboard::ModifiersState,
+    monitor::MonitorHandle,
     window::{Fullscreen, Window},
 };
 
@@ -50,6 +56,14 @@ pub struct WindowState {
     pub panned: PhysicalPosition<f32>,
 
     pub draw_mode: bool,
+    /// Minimized instead of hidden, because nothing else could have brought it back
+    pub minimized: bool,
+
+    /// Monitor the window covers in draw mode, None for whichever its on
+    pub monitor: Option<MonitorHandle>,
+
+    /// Touch id of the pen/finger currently drawing, mouse input is ignored while its down
+    pub pen_id: Option<u64>,
 
     pub temp_actions: Vec<PixelChange>,
 }
@@ -74,6 +88,11 @@ impl WindowState {
             panned: Default::default(),
 
             draw_mode: false,
+            minimized: false,
+
+            monitor: None,
+
+            pen_id: None,
 
             temp_actions: Vec::new(),
         };
@@ -87,28 +106,62 @@ impl WindowState {
 
         self.cursor_pos.previous = self.cursor_pos.current;
 
-        let _ = self.draw_at_cursor();
+        if self.canvas.drawing {
+            let _ = self.draw_at_cursor();
+        }
+    }
+
+    pub fn invert_erasing(&mut self) {
+        // the eraser button only counts while its held, so set it before the stroke picks its tool
+        self.canvas.erasing = !self.canvas.drawing;
+
+        self.invert_drawing();
     }
 
     pub fn enter_draw_mode(&mut self) {
         self.draw_mode = true;
+        self.minimized = false;
 
-        self.window.set_minimized(false);
+        platform::show_window(&self.window);
         self.window
-            .set_fullscreen(Some(Fullscreen::Borderless(None)));
+            .set_fullscreen(Some(Fullscreen::Borderless(self.monitor.clone())));
     }
 
-    pub fn exit_draw_mode(&mut self) {
+    pub fn exit_draw_mode(&mut self, hide: bool) {
         self.draw_mode = false;
 
-        self.canvas.clear_action_stack();
+        // the drawing and its history stay, but the app could be quit from the tray while its hidden
+        self.autosave();
 
         self.window.set_fullscreen(None);
-        self.window.set_minimized(true);
+        self.minimize(hide);
     }
 
-    pub fn minimize(&mut self) {
-        self.window.set_minimized(true);
+    /// Writes the drawing to the autosave file, so its still there next time the app starts
+    pub fn autosave(&self) {
+        let Some(path) = Session::autosave_path() else {
+            return;
+        };
+
+        if let Err(e) = self.canvas.session().save(&path) {
+            eprintln!("couldnt autosave to {}: {e}", path.display());
+        }
+    }
+
+    /// A minimized window comes back into draw mode when its restored from the taskbar
+    pub fn minimize(&mut self, hide: bool) {
+        match hide {
+            true => platform::hide_window(&self.window),
+            false => {
+                self.window.set_minimized(true);
+                self.minimized = true;
+            }
+        }
+    }
+
+    /// Called when the window gets focus, true if it was just restored
+    pub fn restored(&mut self) -> bool {
+        std::mem::take(&mut self.minimized)
     }
 
     pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
@@ -119,6 +172,62 @@ impl WindowState {
         };
     }
 
+    pub fn pen_input(&mut self, touch: Touch) {
+        match self.pen_id {
+            None if touch.phase == TouchPhase::Started => self.pen_id = Some(touch.id),
+            Some(id) if id == touch.id => {}
+            // only one pen/finger draws at a time
+            _ => return,
+        }
+
+        let info = platform::pen_info(touch.id);
+        self.canvas.pen = PenState {
+            pressure: touch.force.map_or(1.0, |f| f.normalized() as f32),
+            tilt: info.and_then(|i| i.tilt),
+            eraser: info.is_some_and(|i| i.eraser),
+        };
+
+        match touch.phase {
+            TouchPhase::Started => {
+                self.cursor_pos.set(Some(touch.location));
+
+                if !self.canvas.drawing {
+                    self.invert_drawing();
+                }
+            }
+            TouchPhase::Moved => self.cursor_moved(touch.location),
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.cursor_moved(touch.location);
+
+                if self.canvas.drawing {
+                    self.invert_drawing();
+                }
+
+                self.pen_id = None;
+                self.canvas.pen = PenState::default();
+            }
+        }
+    }
+
+    /// Finishes the stroke being drawn as if the button was let go, for when that release isnt going to arrive
+    pub fn end_stroke(&mut self) {
+        if self.canvas.drawing {
+            self.invert_drawing();
+        }
+
+        self.canvas.erasing = false;
+        self.pen_id = None;
+        self.canvas.pen = PenState::default();
+    }
+
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+
+        self.canvas.constrain_shape = modifiers.shift_key();
+        self.canvas.shape_from_center = modifiers.alt_key();
+        self.canvas.refresh_shape();
+    }
+
     pub fn cursor_left(&mut self) {
         self.cursor_pos.set(None);
     }
@@ -131,7 +240,7 @@ impl WindowState {
     pub fn toggle_fullscreen(&self) {
         let fullscreen = match self.window.fullscreen() {
             Some(_) => None,
-            None => Some(Fullscreen::Borderless(None)),
+            None => Some(Fullscreen::Borderless(self.monitor.clone())),
         };
 
         self.window.set_fullscreen(fullscreen)
@@ -139,14 +248,6 @@ impl WindowState {
 
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
         self.canvas.resize_canvas(size);
-
-        // FIXME
-        // when the window is resized we fill the buffer with default color again
-        // should probably not do this, will figure out later
-        //
-        // ideally we want to not wipe the buffer, but only fill the spots that have become available because of the resize
-        // and also map all of the previous canvas into the new size since it will be offset after the resize
-        let _ = self.canvas.fill(None);
     }
 
     pub fn draw_at_cursor(&mut self) -> Result<(), Box<dyn Error>> {
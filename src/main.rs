@@ -1,13 +1,18 @@
 #![allow(unused_imports, dead_code)]
 
-use std::error::Error;
+use std::{error::Error, path::Path};
 
 use app::{
-    apphandler::{TrayEvent, UserEvent},
+    apphandler::UserEvent,
+    bindings::Bindings,
+    cli::{Options, USAGE},
+    config,
     program::Application,
+    settings::Settings,
 };
+use modules::{canvas::Canvas, session::Session};
+use platform::Tray;
 use softbuffer::{Context, Surface};
-use trayicon::{Icon, MenuBuilder, TrayIcon, TrayIconBuilder};
 use winit::{
     event::{DeviceEvent, ElementState},
     event_loop::{DeviceEvents, EventLoop, EventLoopBuilder},
@@ -17,27 +22,111 @@ use winit::{
 mod app;
 mod art;
 mod modules;
+mod platform;
+
+/// Loads the keybindings from the config file, printing anything wrong with it
+fn load_bindings(options: &Options) -> (Bindings, usize) {
+    let Some(path) = options.config_path() else {
+        return (Bindings::default(), 0);
+    };
+
+    let (bindings, errors) = config::load_bindings(&path);
+
+    for e in &errors {
+        eprintln!("{}: {e}", path.display());
+    }
+
+    (bindings, errors.len())
+}
+
+fn load_settings() -> Settings {
+    let Some(path) = Settings::default_path() else {
+        return Settings::default();
+    };
+
+    Settings::load(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {e}, using the default settings", path.display());
+        Settings::default()
+    })
+}
+
+/// `--check-bindings`, reports problems with the bindings instead of starting
+fn check_bindings(options: &Options) -> Result<(), Box<dyn Error>> {
+    let (bindings, config_errors) = load_bindings(options);
+    let issues = bindings.validate();
+
+    for issue in &issues {
+        match issue.is_error() {
+            true => eprintln!("error: {issue}"),
+            false => eprintln!("note: {issue}"),
+        }
+    }
+
+    let errors = config_errors + issues.iter().filter(|i| i.is_error()).count();
+    match errors {
+        0 => {
+            println!("bindings ok");
+            Ok(())
+        }
+        n => Err(format!("found {n} problem(s) with the bindings").into()),
+    }
+}
+
+/// `--export`, renders a session without opening a window
+fn export(options: &Options, session: &Path, png: &Path) -> Result<(), Box<dyn Error>> {
+    let session =
+        Session::load(session).map_err(|e| format!("couldnt load {}: {e}", session.display()))?;
+
+    let mut canvas = Canvas::headless(session.size());
+    canvas.restore(session)?;
+    if options.background.is_some() {
+        canvas.background = options.background;
+    }
+
+    canvas.save_png(png)?;
+    println!("saved {}", png.display());
+
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let icon = include_bytes!("../assets/pencil.ico");
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    if options.help {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    if options.version {
+        println!("draw {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    if options.check_bindings {
+        return check_bindings(&options);
+    }
+
+    if let Some((session, png)) = &options.export {
+        return export(&options, session, png);
+    }
 
     let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
     event_loop.listen_device_events(DeviceEvents::Always);
 
-    let _tray_proxy = event_loop.create_proxy();
     let _loop_proxy = event_loop.create_proxy();
 
-    let _ = _loop_proxy.send_event(UserEvent::StartMinimized);
+    let _ = _loop_proxy.send_event(match options.draw_mode {
+        true => UserEvent::StartDrawMode,
+        false => UserEvent::StartMinimized,
+    });
 
-    let tray = TrayIconBuilder::new()
-        .sender(move |e: &UserEvent| {
-            let _ = _tray_proxy.send_event(*e);
-        })
-        .icon_from_buffer(icon)
-        .on_right_click(TrayEvent::RightClick.into())
-        .menu(MenuBuilder::new().item("E&xit", TrayEvent::Exit.into()))
-        .build()
-        .unwrap();
+    let tray = Tray::new(&event_loop)?;
 
     std::thread::spawn(move || loop {
         let _ = _loop_proxy.send_event(UserEvent::Redraw);
@@ -47,7 +136,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         std::thread::sleep(std::time::Duration::from_millis(16));
     });
 
-    let mut state = Application::new(&event_loop, tray);
+    let (bindings, _) = load_bindings(&options);
+    let settings = load_settings();
+
+    let mut state = Application::new(&event_loop, tray, bindings, settings, options);
 
     event_loop.run_app(&mut state).map_err(Into::into)
 }
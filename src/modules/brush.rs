@@ -0,0 +1,282 @@
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalPosition;
+
+/// How the edge of a brush gets rasterized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BrushEdge {
+    /// Every pixel is either fully inked or untouched
+    #[default]
+    Hard,
+    /// Edge pixels are blended by how much of them the brush covers
+    Smooth,
+}
+
+impl BrushEdge {
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Hard => Self::Smooth,
+            Self::Smooth => Self::Hard,
+        }
+    }
+
+    /// Radius that roughly matches the size of the hard edged disc for the same brush size
+    pub fn smooth_radius(brush_size: f64) -> f64 {
+        (brush_size - 0.5).max(0.5)
+    }
+}
+
+pub fn get_line_points(
+    prev: PhysicalPosition<f64>,
+    curr: PhysicalPosition<f64>,
+) -> Vec<(i32, i32)> {
+    let mut points: Vec<(i32, i32)> = vec![];
+
+    let (x1, y1) = (prev.x as i32, prev.y as i32);
+    let (x2, y2) = (curr.x as i32, curr.y as i32);
+
+    let dx = (x2 - x1).abs();
+    let dy = (y2 - y1).abs();
+    let sx = if x1 < x2 { 1 } else { -1 };
+    let sy = if y1 < y2 { 1 } else { -1 };
+
+    let mut err = dx - dy;
+    let mut x = x1;
+    let mut y = y1;
+
+    loop {
+        points.push((x, y));
+
+        if x == x2 && y == y2 {
+            break;
+        }
+
+        let e2 = 2 * err;
+        if e2 > -dy {
+            err -= dy;
+            x += sx;
+        }
+        if e2 < dx {
+            err += dx;
+            y += sy;
+        }
+    }
+
+    points
+}
+
+pub fn get_circle_points(location: PhysicalPosition<f64>, brush_size: u32) -> Vec<(i32, i32)> {
+    let radius = brush_size as i32 - 1;
+    let mut points = Vec::<(i32, i32)>::new();
+
+    let x_center = location.x as i32;
+    let y_center = location.y as i32;
+
+    // i still want to draw 1 pixel sometimes
+    if brush_size <= 1 {
+        return vec![(x_center, y_center)];
+    };
+
+    // Thanks stackoverflow, i dont like math.
+    // Very Bad "Algorithm"
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            if x * x + y * y < radius * radius + radius {
+                points.push((x_center + x, y_center + y));
+            }
+        }
+    }
+
+    // sort vec and then call dedup, which removes all consecutive repeating values :D
+    points.sort();
+    points.dedup();
+    points
+}
+
+/// Every pixel covered by dragging a brush from `prev` to `curr`, the brush size goes from
+/// `size_prev` to `size_curr` along the way
+pub fn get_segment_points(
+    prev: PhysicalPosition<f64>,
+    curr: PhysicalPosition<f64>,
+    size_prev: f64,
+    size_curr: f64,
+) -> Vec<((i32, i32), f64)> {
+    let line = get_line_points(prev, curr);
+    let steps = (line.len() - 1).max(1) as f64;
+
+    let mut points = Vec::new();
+
+    for (i, p) in line.into_iter().enumerate() {
+        let t = i as f64 / steps;
+        let size = (size_prev + (size_curr - size_prev) * t).round().max(1.0) as u32;
+
+        points.extend(
+            get_circle_points(p.into(), size)
+                .into_iter()
+                .map(|p| (p, t)),
+        );
+    }
+
+    points
+}
+
+/// Pixels covered by a round brush dragged from `prev` to `curr`, paired with how much of the pixel
+/// is covered (0..=1). The radius goes from `radius_prev` to `radius_curr` along the way
+pub fn get_segment_coverage(
+    prev: PhysicalPosition<f64>,
+    curr: PhysicalPosition<f64>,
+    radius_prev: f64,
+    radius_curr: f64,
+) -> Vec<((i32, i32), f32)> {
+    let reach = radius_prev.max(radius_curr) + 1.0;
+
+    let x_min = (prev.x.min(curr.x) - reach).floor() as i32;
+    let x_max = (prev.x.max(curr.x) + reach).ceil() as i32;
+    let y_min = (prev.y.min(curr.y) - reach).floor() as i32;
+    let y_max = (prev.y.max(curr.y) + reach).ceil() as i32;
+
+    let mut points = Vec::new();
+
+    for y in y_min..=y_max {
+        for x in x_min..=x_max {
+            // the cursor position gets floored into a pixel, so measure from the pixel corner
+            // to keep smooth strokes lined up with hard ones
+            let (d, t) =
+                project_onto_segment(PhysicalPosition::new(x as f64, y as f64), prev, curr);
+            let radius = radius_prev + (radius_curr - radius_prev) * t;
+            let coverage = (radius + 0.5 - d).clamp(0.0, 1.0) as f32;
+
+            if coverage > 0.0 {
+                points.push(((x, y), coverage));
+            }
+        }
+    }
+
+    points
+}
+
+pub fn distance(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+pub fn distance_to_segment(
+    p: PhysicalPosition<f64>,
+    a: PhysicalPosition<f64>,
+    b: PhysicalPosition<f64>,
+) -> f64 {
+    project_onto_segment(p, a, b).0
+}
+
+/// Distance from `p` to the closest point on the segment, and how far along (0..=1) that point is
+pub fn project_onto_segment(
+    p: PhysicalPosition<f64>,
+    a: PhysicalPosition<f64>,
+    b: PhysicalPosition<f64>,
+) -> (f64, f64) {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let len_sq = dx * dx + dy * dy;
+
+    if len_sq == 0.0 {
+        return (distance(p, a), 0.0);
+    }
+
+    let t = (((p.x - a.x) * dx + (p.y - a.y) * dy) / len_sq).clamp(0.0, 1.0);
+
+    (
+        distance(p, PhysicalPosition::new(a.x + t * dx, a.y + t * dy)),
+        t,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+    use super::BrushEdge;
+    use crate::modules::canvas::{Canvas, CLEAR_BG_COLOR, DEFAULT_BRUSH_COLOR};
+    use crate::modules::pen::PenState;
+    use crate::modules::pixels::blend_over;
+    use crate::modules::stroke::{Stroke, Tool};
+
+    const WIDTH: u32 = 9;
+
+    const O: u32 = CLEAR_BG_COLOR;
+    const I: u32 = DEFAULT_BRUSH_COLOR;
+
+    /// A size 2 pen stroke through `points`, rendered onto a fresh canvas
+    fn render(edge: BrushEdge, color: u32, points: &[(f64, f64)]) -> Vec<u32> {
+        let mut canvas = Canvas::headless(PhysicalSize::new(WIDTH, 7));
+
+        let mut stroke = Stroke::new(color, 2, Tool::Pen, edge);
+        for &(x, y) in points {
+            stroke.push(
+                PhysicalPosition::new(x, y),
+                Duration::ZERO,
+                &PenState::default(),
+            );
+        }
+
+        canvas.strokes.push(stroke);
+        canvas.rerender();
+        canvas.pixels().to_vec()
+    }
+
+    #[rustfmt::skip]
+    const HARD: [u32; 63] = [
+        O, O, O, O, O, O, O, O, O,
+        O, O, I, I, O, O, O, O, O,
+        O, I, I, I, I, I, O, O, O,
+        O, O, I, I, I, I, I, O, O,
+        O, O, O, O, I, I, I, I, O,
+        O, O, O, O, O, O, I, O, O,
+        O, O, O, O, O, O, O, O, O,
+    ];
+
+    // the edge pixels are the brush color premultiplied by how much of them is covered
+    const A: u32 = 0x95957864;
+    const B: u32 = 0xa8a88670;
+    const C: u32 = 0x36362b24;
+
+    #[rustfmt::skip]
+    const SMOOTH: [u32; 63] = [
+        O, O, O, O, O, O, O, O, O,
+        O, A, I, B, C, O, O, O, O,
+        O, I, I, I, I, B, C, O, O,
+        O, A, I, I, I, I, I, A, O,
+        O, O, C, B, I, I, I, I, O,
+        O, O, O, O, C, B, I, A, O,
+        O, O, O, O, O, O, O, O, O,
+    ];
+
+    #[test]
+    fn hard_edge_matches_golden() {
+        let pixels = render(BrushEdge::Hard, I, &[(2.0, 2.0), (6.0, 4.0)]);
+        assert_eq!(pixels, HARD);
+    }
+
+    #[test]
+    fn smooth_edge_matches_golden() {
+        let pixels = render(BrushEdge::Smooth, I, &[(2.0, 2.0), (6.0, 4.0)]);
+        assert_eq!(pixels, SMOOTH);
+    }
+
+    #[test]
+    fn smooth_edge_is_premultiplied() {
+        for pixel in render(BrushEdge::Smooth, 0x80ff4020, &[(2.0, 2.0), (6.0, 4.0)]) {
+            let alpha = pixel >> 24;
+            assert!([16, 8, 0]
+                .iter()
+                .all(|shift| (pixel >> shift) & 0xff <= alpha));
+        }
+    }
+
+    #[test]
+    fn translucent_color_is_premultiplied() {
+        assert_eq!(blend_over(CLEAR_BG_COLOR, 0x80ff4020, 1.0), 0x80802010);
+        assert_eq!(blend_over(CLEAR_BG_COLOR, 0xffffffff, 0.5), 0x80808080);
+
+        let pixels = render(BrushEdge::Hard, 0x80ff4020, &[(4.0, 3.0)]);
+        assert_eq!(pixels[(4 + 3 * WIDTH) as usize], 0x80802010);
+    }
+}
@@ -1,326 +1,1725 @@
-use softbuffer::Surface;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     error::Error,
-    num::NonZeroU32,
+    path::Path,
     sync::Arc,
     time::{Duration, Instant},
 };
-use wgpu::rwh::DisplayHandle;
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     window::Window,
 };
 
+use super::brush::BrushEdge;
+use super::export;
+use super::history::{FlatTree, PixelChange, PixelDiff, UndoTree};
+use super::layer::{self, Layer, LayerProps, OPACITY_STEP};
+use super::overlay::{draw_hint, draw_notice, draw_number};
+use super::pen::{PenState, PressureCurve};
+use super::pixels::{blend_over, over, PixelBuffer, PixelTarget};
+use super::presenter::{Presenter, SurfacePresenter};
+use super::session::{Branch, Session, SessionLayer, SessionSettings, Step};
+use super::shapes;
+use super::smoothing::{Smoother, Smoothing};
+use super::stroke::{Stroke, StrokePoint, Tool};
+use super::svg;
 use crate::app::program::Application;
-use crate::art::numbers::get_art;
+use crate::platform;
 
 const DEFAULT_BG_COLOR: u32 = 0xff181818;
-const CLEAR_BG_COLOR: u32 = 0x00000000;
-const DEFAULT_BRUSH_COLOR: u32 = 0xffffccaa;
+pub const CLEAR_BG_COLOR: u32 = 0x00000000;
+pub const DEFAULT_BRUSH_COLOR: u32 = 0xffffccaa;
+
+pub const BRUSH_SIZE: u32 = 2;
+
+/// How long a notice stays on screen
+const NOTICE_TIME: Duration = Duration::from_secs(5);
+/// How long the brush size stays in the corner after it changes
+const BRUSH_NUMBER_TIME: Duration = Duration::from_secs(1);
+
+const HIGHLIGHTER_ALPHA: u32 = 0x60;
+const HIGHLIGHTER_WIDTH: u32 = 4;
+
+/// How much memory the undo history can use before the oldest steps get dropped
+pub const HISTORY_LIMIT: usize = 256 * 1024 * 1024;
+
+/// How far moving a layer with the arrow keys moves it
+pub const NUDGE_STEP: f64 = 10.0;
+
+/// What happened to the strokes in a single action
+#[derive(Debug, Clone)]
+pub enum StrokeChange {
+    Added(usize, Stroke),
+    Edited {
+        index: usize,
+        before: Stroke,
+        after: Stroke,
+    },
+    /// Strokes wiped by the stroke eraser, sorted by their index before removal
+    Removed(Vec<(usize, Stroke)>),
+    /// Strokes pasted on top of everything else
+    Pasted(Vec<Stroke>),
+    /// The whole list swapped at once, like clearing the canvas or transforming everything on it
+    Replaced {
+        before: Vec<Stroke>,
+        after: Vec<Stroke>,
+    },
+    /// A new empty layer
+    AddedLayer(LayerProps),
+    /// The layer was put on top of the one under it, its strokes went on top of that ones
+    MergedLayer {
+        props: LayerProps,
+        strokes: Vec<Stroke>,
+    },
+}
 
-const BRUSH_SIZE: u32 = 2;
+impl StrokeChange {
+    /// Whether the change adds or removes layers instead of changing the strokes on one
+    pub fn changes_layers(&self) -> bool {
+        matches!(
+            self,
+            StrokeChange::AddedLayer(_) | StrokeChange::MergedLayer { .. }
+        )
+    }
+}
 
-#[derive(Debug, Clone, Copy)]
-pub struct PixelChange {
-    buf_index: u32,
-    before_data: u32,
-    after_data: u32,
+/// What the eraser tool removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EraseMode {
+    /// Clears pixels back to transparency like a normal brush would paint them
+    #[default]
+    Pixels,
+    /// Removes every stroke the eraser touches in one go
+    Strokes,
 }
 
-type DrawChangeAction = Vec<PixelChange>;
+#[derive(Debug, Clone)]
+pub struct DrawChangeAction {
+    /// Layer the change was made on, or the one that was added or merged
+    pub layer: usize,
+    pub pixels: PixelDiff,
+    pub stroke: StrokeChange,
+}
+
+impl DrawChangeAction {
+    /// Roughly how much memory the action holds on to, strokes included
+    pub fn bytes(&self) -> usize {
+        let stroke =
+            |s: &Stroke| size_of::<Stroke>() + s.points.capacity() * size_of::<StrokePoint>();
+
+        self.pixels.bytes()
+            + match &self.stroke {
+                StrokeChange::Added(_, added) => stroke(added),
+                StrokeChange::Edited { before, after, .. } => stroke(before) + stroke(after),
+                StrokeChange::Removed(removed) => removed.iter().map(|(_, s)| stroke(s)).sum(),
+                StrokeChange::Pasted(pasted) => pasted.iter().map(stroke).sum(),
+                StrokeChange::Replaced { before, after } => {
+                    before.iter().chain(after).map(stroke).sum()
+                }
+                StrokeChange::AddedLayer(_) => 0,
+                StrokeChange::MergedLayer { strokes, .. } => strokes.iter().map(stroke).sum(),
+            }
+    }
+}
 
 pub struct Canvas {
-    pub surface: Surface<DisplayHandle<'static>, Arc<Window>>,
+    pub target: Box<dyn PixelTarget>,
+
+    /// None when running headless
+    presenter: Option<Box<dyn Presenter>>,
 
     pub drawing: bool,
 
     pub brush_size: u32,
     brush_changed_at: Instant,
     pub brush_color: u32,
+    pub brush_edge: BrushEdge,
+    pub tool: Tool,
+    pub erase_mode: EraseMode,
+    /// Eraser held down on top of whatever tool is picked
+    pub erasing: bool,
+
+    /// Shift: 45° lines and square shapes
+    pub constrain_shape: bool,
+    /// Alt: shapes grow out of where the drag started
+    pub shape_from_center: bool,
+
+    /// Latest pressure/tilt from the pointer
+    pub pen: PenState,
+    pub pressure_curve: PressureCurve,
 
     pub canvas_size: PhysicalSize<u32>,
 
-    pub action_stack: Vec<DrawChangeAction>,
-    pub redo_stack: Vec<DrawChangeAction>,
+    /// Opaque color shown behind the drawing, like a whiteboard. None leaves the window see through
+    pub background: Option<u32>,
+
+    /// Text shown on top of the canvas without being drawn into it, like a pending key sequence
+    pub hint: Option<String>,
+    /// Message shown for a few seconds, like a config file that failed to load. The bool marks errors
+    notice: Option<(String, bool, Instant)>,
+    /// Brush size shown in the corner for a moment after it changes
+    brush_number: Option<(u32, Instant)>,
+    /// What actually gets presented when theres a hint on top
+    frame: Vec<u32>,
+
+    /// Every committed stroke on the layer being drawn on in draw order, the pixel buffer is derived from these
+    pub strokes: Vec<Stroke>,
+    /// Bottom to top. The layer being drawn on only keeps its settings here,
+    /// its strokes and pixels are `strokes` and `target` until another layer is picked
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
+    current_stroke: Option<(Stroke, Instant)>,
+    pub smoother: Smoother,
+    /// Strokes marked by the stroke eraser so far, and the pixels from before it started
+    stroke_erase: Option<(Vec<usize>, Vec<u32>)>,
+    /// Where the drag for the current shape started, and where the cursor is now
+    shape_drag: Option<(PhysicalPosition<f64>, PhysicalPosition<f64>)>,
+    /// Where the current stroke was last drawn up to
+    last_point: Option<StrokePoint>,
+    /// Original pixel and highest coverage so far for every pixel the current smooth stroke touched
+    stroke_coverage: HashMap<u32, (u32, f32)>,
+    pub selected: Option<usize>,
+    /// Strokes from the last time ink was copied, what pasting puts back
+    pub copied: Vec<Stroke>,
+
+    pub history: UndoTree<DrawChangeAction>,
     pub temp_stack: Vec<PixelChange>,
+    /// Bytes the undo history can take up, the oldest steps go first
+    pub history_limit: usize,
 }
 
 impl Canvas {
     pub fn new(app: &Application, window: Arc<Window>) -> Result<Self, Box<dyn Error>> {
         let c_size = window.inner_size();
-        let surface = Surface::new(app.context.as_ref().unwrap(), window)?;
+        let presenter = SurfacePresenter::new(app, window)?;
+
+        let mut canvas = Self::with_target(Box::new(PixelBuffer::new(c_size, CLEAR_BG_COLOR)));
+        canvas.presenter = Some(Box::new(presenter));
+
+        Ok(canvas)
+    }
+
+    /// Canvas that only draws into memory, nothing is ever shown
+    pub fn headless(size: PhysicalSize<u32>) -> Self {
+        Self::with_target(Box::new(PixelBuffer::new(size, CLEAR_BG_COLOR)))
+    }
+
+    pub fn with_target(target: Box<dyn PixelTarget>) -> Self {
+        let c_size = target.size();
 
-        Ok(Self {
-            surface,
+        Self {
+            target,
+            presenter: None,
 
             drawing: false,
             brush_size: BRUSH_SIZE,
             brush_changed_at: Instant::now(),
             brush_color: DEFAULT_BRUSH_COLOR,
+            brush_edge: BrushEdge::default(),
+            tool: Tool::Pen,
+            erase_mode: EraseMode::default(),
+            erasing: false,
+
+            constrain_shape: false,
+            shape_from_center: false,
+
+            pen: PenState::default(),
+            pressure_curve: PressureCurve::default(),
             canvas_size: c_size,
 
-            action_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            background: None,
+            hint: None,
+            notice: None,
+            brush_number: None,
+            frame: Vec::new(),
+
+            strokes: Vec::new(),
+            layers: vec![Layer::placeholder(LayerProps::default())],
+            active_layer: 0,
+            current_stroke: None,
+            smoother: Smoother::new(Smoothing::default()),
+            stroke_erase: None,
+            shape_drag: None,
+            last_point: None,
+            stroke_coverage: HashMap::new(),
+            selected: None,
+            copied: Vec::new(),
+
+            history: UndoTree::new(),
             temp_stack: Vec::new(),
-        })
+            history_limit: HISTORY_LIMIT,
+        }
     }
 
     pub fn present(&mut self) -> Result<(), Box<dyn Error>> {
-        let buf = self.surface.buffer_mut()?;
-        buf.present().expect("failed to present buffer");
+        if self.presenter.is_none() {
+            return Ok(());
+        }
+
+        if self
+            .notice
+            .as_ref()
+            .is_some_and(|(_, _, at)| at.elapsed() > NOTICE_TIME)
+        {
+            self.notice = None;
+        }
+
+        if (self.brush_number).is_some_and(|(_, at)| at.elapsed() > BRUSH_NUMBER_TIME) {
+            self.brush_number = None;
+        }
+
+        // a single layer thats fully shown is already exactly what goes on screen
+        let plain = match &self.layers[..] {
+            [layer] => layer.props.visible && layer.props.opacity >= 1.0,
+            _ => false,
+        };
+
+        let overlaid = self.hint.is_some() || self.notice.is_some() || self.brush_number.is_some();
+        if plain && !overlaid && self.background.is_none() {
+            let presenter = self.presenter.as_mut().unwrap();
+            return presenter.present(self.target.pixels());
+        }
+
+        let mut frame = std::mem::take(&mut self.frame);
+        self.composite(&mut frame, self.background);
+
+        if let Some(hint) = &self.hint {
+            draw_hint(&mut frame, self.canvas_size, hint);
+        }
+
+        if let Some((notice, error, _)) = &self.notice {
+            draw_notice(&mut frame, self.canvas_size, notice, *error);
+        }
+
+        if let Some((n, _)) = self.brush_number {
+            draw_number(&mut frame, self.canvas_size, n, self.brush_color);
+        }
+
+        let presented = self.presenter.as_mut().unwrap().present(&frame);
+        self.frame = frame;
+
+        presented
+    }
+
+    /// Every shown layer on top of each other in `frame`, with `background` under them
+    fn composite(&self, frame: &mut Vec<u32>, background: Option<u32>) {
+        let bg = background.map_or(0, |bg| blend_over(0, bg, 1.0));
+
+        frame.clear();
+        frame.resize(self.target.pixels().len(), bg);
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            if layer.props.shown() {
+                layer::composite(frame, self.layer_pixels(i), layer.props.opacity);
+            }
+        }
+    }
+
+    pub fn show_notice(&mut self, text: impl Into<String>, error: bool) {
+        self.notice = Some((text.into(), error, Instant::now()));
+    }
+
+    /// Pixels of the layer being drawn on
+    pub fn pixels(&self) -> &[u32] {
+        self.target.pixels()
+    }
+
+    /// Every shown layer put together, without the background
+    pub fn composited(&self) -> Vec<u32> {
+        let mut pixels = Vec::new();
+        self.composite(&mut pixels, None);
+        pixels
+    }
+
+    /// The layers with the background under them, what ends up in exported images
+    pub fn flattened(&self) -> Vec<u32> {
+        let mut pixels = Vec::new();
+        self.composite(&mut pixels, self.background);
+        pixels
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        export::save_png(&self.flattened(), self.canvas_size, path)
+    }
+
+    /// Writes the strokes out as vector graphics instead of pixels
+    pub fn save_svg(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let layers: Vec<_> = (self.layers.iter().enumerate())
+            .filter(|(_, layer)| layer.props.shown())
+            .map(|(i, layer)| (self.layer_strokes(i), layer.props.opacity))
+            .collect();
+
+        std::fs::write(
+            path,
+            svg::encode(&layers, self.canvas_size, self.background),
+        )?;
 
         Ok(())
     }
 
+    /// Puts the drawing on the clipboard, with `crop` only the part that has ink on it
+    pub fn copy_to_clipboard(&self, crop: bool) -> Result<(), Box<dyn Error>> {
+        let pixels = self.flattened();
+
+        if !crop {
+            return platform::copy_image(&pixels, self.canvas_size);
+        }
+
+        // the background covers everything, so the ink has to be found before its added
+        let (origin, size) = export::ink_bounds(&self.composited(), self.canvas_size)
+            .ok_or("theres nothing drawn to copy")?;
+
+        platform::copy_image(&export::crop(&pixels, self.canvas_size, origin, size), size)
+    }
+
     pub fn invert_drawing(&mut self) {
         self.drawing = !self.drawing;
 
         match self.drawing {
             true => {
                 self.temp_stack.clear();
-                self.redo_stack.clear();
+
+                self.stroke_coverage.clear();
+                self.smoother.begin();
+                self.shape_drag = None;
+                self.last_point = None;
+
+                // without a stroke to draw into the press and drag dont do anything
+                if !self.layer_editable() {
+                    self.current_stroke = None;
+                    return;
+                }
+
+                self.current_stroke = Some((self.new_stroke(), Instant::now()));
+
+                if self.active_tool() == Tool::Eraser && self.erase_mode == EraseMode::Strokes {
+                    self.stroke_erase = Some((Vec::new(), self.target.pixels().to_vec()));
+                }
             }
             false => {
+                for p in self.smoother.finish() {
+                    self.extend_stroke(p);
+                }
+                self.last_point = None;
+
+                if let Some((marked, old_pixels)) = self.stroke_erase.take() {
+                    self.current_stroke = None;
+                    self.commit_stroke_erase(marked, old_pixels);
+                    return;
+                }
+
                 // i assume this is slow in some way but, it doesnt seem to make any noticeable difference even with 10/15 brush size
-                self.temp_stack
-                    .sort_by(|a, b| a.buf_index.cmp(&b.buf_index));
-                self.temp_stack.dedup_by_key(|a| a.buf_index);
+                // keep the first before and the last after, smooth brushes can touch a pixel more than once
+                self.temp_stack.sort_by_key(|a| a.buf_index);
+                self.temp_stack.dedup_by(|later, first| {
+                    if later.buf_index != first.buf_index {
+                        return false;
+                    }
+
+                    first.after_data = later.after_data;
+                    true
+                });
+                self.temp_stack.retain(|c| c.before_data != c.after_data);
+
+                let stroke = match self.current_stroke.take() {
+                    Some((stroke, _)) => stroke,
+                    None => self.new_stroke(),
+                };
+
+                // a click that didnt change anything, like erasing where theres nothing drawn, isnt worth a step
+                if self.temp_stack.is_empty() {
+                    return;
+                }
 
-                self.action_stack.push(self.temp_stack.clone());
+                self.strokes.push(stroke.clone());
+
+                let pixels = PixelDiff::from_changes(self.temp_stack.drain(..));
+                self.push_action(DrawChangeAction {
+                    layer: self.active_layer,
+                    pixels,
+                    stroke: StrokeChange::Added(self.strokes.len() - 1, stroke),
+                });
             }
         }
     }
 
-    pub fn resize_canvas(&mut self, size: PhysicalSize<u32>) {
-        let (width, height) = match (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) {
-            (Some(width), Some(height)) => (width, height),
-            _ => return,
+    fn new_stroke(&self) -> Stroke {
+        let tool = self.active_tool();
+
+        // every pixel only ever gets the strongest coverage of the stroke blended onto what was there before,
+        // so a translucent stroke is composited once no matter how often it crosses itself
+        let (color, width) = match tool {
+            Tool::Highlighter => (
+                (self.brush_color & 0x00ffffff) | (HIGHLIGHTER_ALPHA << 24),
+                self.brush_size * HIGHLIGHTER_WIDTH,
+            ),
+            _ => (self.brush_color, self.brush_size),
+        };
+
+        let mut stroke = Stroke::new(color, width, tool, self.brush_edge);
+        stroke.pressure = self.pressure_curve;
+
+        stroke
+    }
+
+    /// Flipping a tablet pen over (or holding the erase button) erases no matter which tool is picked
+    pub fn active_tool(&self) -> Tool {
+        match self.pen.eraser || self.erasing {
+            true => Tool::Eraser,
+            false => self.tool,
+        }
+    }
+
+    pub fn toggle_eraser(&mut self) {
+        self.tool = match self.tool {
+            Tool::Eraser => Tool::Pen,
+            _ => Tool::Eraser,
+        };
+    }
+
+    pub fn toggle_erase_mode(&mut self) {
+        self.erase_mode = match self.erase_mode {
+            EraseMode::Pixels => EraseMode::Strokes,
+            EraseMode::Strokes => EraseMode::Pixels,
+        };
+    }
+
+    /// Marks every stroke under the eraser, and hides them until the erase is committed
+    fn erase_strokes_at(&mut self, location: PhysicalPosition<f64>) {
+        let Some((marked, _)) = self.stroke_erase.as_mut() else {
+            return;
         };
 
+        let reach = self.brush_size as f64;
+        let before = marked.len();
+
+        for (i, stroke) in self.strokes.iter().enumerate() {
+            if stroke.tool != Tool::Eraser
+                && !marked.contains(&i)
+                && stroke.hit_test(location, reach)
+            {
+                marked.push(i);
+            }
+        }
+
+        if marked.len() != before {
+            let marked = marked.clone();
+            render_strokes(
+                self.strokes
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !marked.contains(i))
+                    .map(|(_, s)| s),
+                self.target.as_mut(),
+                1.0,
+            );
+        }
+    }
+
+    fn commit_stroke_erase(&mut self, mut marked: Vec<usize>, old_pixels: Vec<u32>) {
+        if marked.is_empty() {
+            return;
+        }
+
+        marked.sort();
+
+        let mut removed = Vec::new();
+        for &i in marked.iter().rev() {
+            removed.push((i, self.strokes.remove(i)));
+        }
+        removed.reverse();
+
+        self.rerender();
+
+        self.push_action(DrawChangeAction {
+            layer: self.active_layer,
+            pixels: PixelDiff::between(&old_pixels, self.target.pixels()),
+            stroke: StrokeChange::Removed(removed),
+        });
+    }
+
+    pub fn toggle_brush_edge(&mut self) {
+        self.brush_edge = self.brush_edge.toggled();
+    }
+
+    pub fn resize_canvas(&mut self, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        let old_size = self.canvas_size;
         self.canvas_size = size;
 
-        // TODO: figure out how to resize this and have the new area be filled in with a default color
-        // without overwriting the rest of the canvas
-        self.surface
-            .resize(width, height)
-            .expect("failed to resize canvas surface");
+        // the window grows/shrinks from its top left corner, so keeping the old pixels there
+        // keeps them in the same spot on screen
+        self.target.resize(size, CLEAR_BG_COLOR);
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            if i != self.active_layer {
+                layer.target.resize(size, CLEAR_BG_COLOR);
+            }
+        }
+
+        if old_size != size {
+            self.remap_history(old_size, size);
+        }
+
+        if let Some(presenter) = self.presenter.as_mut() {
+            presenter
+                .resize(size)
+                .expect("failed to resize canvas surface");
+        }
     }
 
-    pub fn fill(&mut self, color: Option<u32>) -> Result<(), Box<dyn Error>> {
-        let mut buffer = self.surface.buffer_mut()?;
-        buffer.fill(color.unwrap_or(CLEAR_BG_COLOR));
+    /// Moves every recorded change over to the new buffer layout,
+    /// changes that ended up outside of the canvas are dropped
+    fn remap_history(&mut self, old: PhysicalSize<u32>, new: PhysicalSize<u32>) {
+        let (old, new) = ((old.width, old.height), (new.width, new.height));
 
-        Ok(())
+        self.history
+            .actions_mut()
+            .for_each(|a| a.pixels.remap(old, new));
+
+        self.temp_stack.retain_mut(|change| {
+            let x = change.buf_index % old.0;
+            let y = change.buf_index / old.0;
+
+            if x >= new.0 || y >= new.1 {
+                return false;
+            }
+
+            change.buf_index = x + y * new.0;
+            true
+        });
     }
 
-    pub fn clear_action_stack(&mut self) {
-        self.action_stack.clear();
-        self.redo_stack.clear();
-        self.temp_stack.clear();
+    /// Covers the whole canvas in `color`. Its kept as a stroke like everything else, so it can be undone
+    pub fn fill(&mut self, color: u32) {
+        let size = self.canvas_size;
+
+        let mut stroke = Stroke::new(color, 1, Tool::Fill, BrushEdge::Hard);
+        for (x, y) in [
+            (0, 0),
+            (size.width.saturating_sub(1), size.height.saturating_sub(1)),
+        ] {
+            let corner = PhysicalPosition::new(x as f64, y as f64);
+            stroke.push(corner, Duration::ZERO, &PenState::default());
+        }
+
+        self.commit(StrokeChange::Added(self.strokes.len(), stroke));
     }
 
-    pub fn undo(&mut self) {
-        const UNDO: bool = false;
+    /// Removes every stroke on the layer being drawn on, undoing brings them all back
+    pub fn clear(&mut self) {
+        if self.strokes.is_empty() {
+            return;
+        }
 
-        if let Some(action) = self.action_stack.pop() {
-            self.apply_changes(&action, UNDO);
-            self.redo_stack.push(action);
-        };
+        self.commit(StrokeChange::Replaced {
+            before: self.strokes.clone(),
+            after: Vec::new(),
+        });
     }
 
-    pub fn redo(&mut self) {
-        const REDO: bool = true;
+    /// Puts `strokes` on top of the drawing as a single step
+    pub fn paste(&mut self, strokes: Vec<Stroke>) {
+        if strokes.is_empty() {
+            return;
+        }
 
-        if let Some(action) = self.redo_stack.pop() {
-            self.apply_changes(&action, REDO);
-            self.action_stack.push(action);
-        };
+        self.commit(StrokeChange::Pasted(strokes));
     }
 
-    pub fn apply_changes(&mut self, v: &DrawChangeAction, t: bool) {
-        let mut buf = self
-            .surface
-            .buffer_mut()
-            .expect("failed to get buffer while applying changes");
+    /// Keeps the strokes on the layer being drawn on around for `paste_copied`
+    pub fn copy_strokes(&mut self) {
+        self.copied = self.strokes.clone();
+    }
 
-        for change in v {
-            buf[change.buf_index as usize] = match t {
-                true => change.after_data,
-                false => change.before_data,
-            };
+    /// Puts the copied strokes on the layer being drawn on, false when nothing was copied
+    pub fn paste_copied(&mut self) -> bool {
+        if self.copied.is_empty() {
+            return false;
         }
+
+        self.paste(self.copied.clone());
+        true
     }
 
-    pub fn change_brush_size(&mut self, d: i32) {
-        let original = self.brush_size;
+    /// Moves everything on the layer being drawn on as a single step
+    pub fn nudge(&mut self, dx: f64, dy: f64) {
+        self.transform(|stroke| {
+            // fills cover the whole canvas no matter where it moves
+            if stroke.tool != Tool::Fill {
+                stroke.translate(dx, dy);
+            }
+        });
+    }
 
-        let n = (self.brush_size as i32 + d).clamp(1, 10) as u32;
+    /// Changes every stroke at once, like moving or scaling the whole drawing
+    pub fn transform(&mut self, edit: impl FnMut(&mut Stroke)) {
+        let mut after = self.strokes.clone();
+        after.iter_mut().for_each(edit);
 
-        // technically this would also trigger even if the original is the same, but its been 5 seconds so its whatever
-        if self.brush_changed_at.elapsed() < Duration::new(1, 0) || original == n {
+        if after == self.strokes {
+            return;
+        }
+
+        self.commit(StrokeChange::Replaced {
+            before: self.strokes.clone(),
+            after,
+        });
+    }
+
+    /// Applies a change to the stroke list and records it as a single undoable step.
+    /// The pixels are rendered from scratch, which is fine for things that touch the whole canvas anyway
+    fn commit(&mut self, change: StrokeChange) {
+        // the stroke being drawn still expects the list it started with
+        if self.drawing || !self.layer_editable() {
             return;
+        }
+
+        let old_pixels = self.target.pixels().to_vec();
+
+        self.apply_stroke_change(&change, true);
+        self.rerender();
+
+        self.push_action(DrawChangeAction {
+            layer: self.active_layer,
+            pixels: PixelDiff::between(&old_pixels, self.target.pixels()),
+            stroke: change,
+        });
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+        self.temp_stack.clear();
+    }
+
+    /// Everything needed to pick the drawing back up later
+    pub fn session(&self) -> Session {
+        let settings = SessionSettings {
+            brush_color: self.brush_color,
+            brush_size: self.brush_size,
+            brush_edge: self.brush_edge,
+            tool: self.tool,
+            erase_mode: self.erase_mode,
+            background: self.background,
         };
 
-        self.brush_size = n;
+        // a drawing that never used layers is saved the way it was before there were any
+        let layered = self.layers.len() > 1 || self.layers[0].props != LayerProps::default();
+
+        let mut session = match layered {
+            true => Session::new(self.canvas_size, settings, Vec::new()),
+            false => Session::new(self.canvas_size, settings, self.strokes.clone()),
+        };
+
+        if layered {
+            session.layers = (self.layers.iter().enumerate())
+                .map(|(i, layer)| SessionLayer {
+                    props: layer.props.clone(),
+                    strokes: self.layer_strokes(i).to_vec(),
+                })
+                .collect();
+            session.active_layer = self.active_layer;
+        }
+
+        let flat = self.history.flatten();
+        let step = |(_, action): &(_, &DrawChangeAction)| Step {
+            layer: action.layer,
+            change: (&action.stroke).into(),
+        };
 
-        let _ = self.draw_number_in_corner(n, None);
+        session.undo = flat.steps[..flat.done].iter().map(step).collect();
+        session.redo = flat.steps[flat.done..flat.line]
+            .iter()
+            .rev()
+            .map(step)
+            .collect();
+        session.branches = flat.steps[flat.line..]
+            .iter()
+            .map(|s| Branch {
+                parent: s.0,
+                step: step(s),
+            })
+            .collect();
+
+        session.min_version = session.required_version();
+
+        session
     }
 
-    pub fn draw_number_in_corner(
-        &mut self,
-        n: u32,
-        offset: Option<PhysicalPosition<u32>>,
-    ) -> Result<(), Box<dyn Error>> {
-        // offset is how far away from the borders we want to be
-        let offset = offset.unwrap_or(PhysicalPosition { x: 20, y: 100 });
-        let num = n;
+    /// Replaces the drawing with a saved one. Only the stroke side of the history is saved,
+    /// the pixels for every step are found by rendering the strokes before and after it
+    pub fn restore(&mut self, session: Session) -> Result<(), Box<dyn Error>> {
+        let size = session.size();
 
-        let size = self.canvas_size;
+        let layers = match session.layers.is_empty() {
+            true => vec![(LayerProps::default(), session.strokes)],
+            false if !session.strokes.is_empty() => {
+                return Err("the session has strokes outside of its layers".into());
+            }
+            false => (session.layers.into_iter())
+                .map(|layer| (layer.props, layer.strokes))
+                .collect(),
+        };
+        let active = session.active_layer.min(layers.len() - 1);
+
+        let parse =
+            |step: Step| Ok::<_, String>((step.layer, StrokeChange::try_from(step.change)?));
+        let undo = session
+            .undo
+            .into_iter()
+            .map(parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let redo = session
+            .redo
+            .into_iter()
+            .map(parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let done = undo.len();
+
+        // undo and redo are a single line of steps, the branches hang off of them
+        let mut steps = Vec::new();
+        for (layer, change) in undo.into_iter().chain(redo.into_iter().rev()) {
+            steps.push((steps.len().checked_sub(1), layer, change));
+        }
+        let line = steps.len();
+        for branch in session.branches {
+            let (layer, change) = parse(branch.step)?;
+            steps.push((branch.parent, layer, change));
+        }
+
+        // rebuilt on the side so a history that doesnt fit leaves the canvas alone
+        let mut scratch = Canvas::headless(self.canvas_size);
+        scratch.replace_layers(layers.clone(), active);
+        for (_, layer, change) in steps[..done].iter().rev() {
+            if !scratch.step_fits(*layer, change, false) {
+                return Err("the undo history doesnt match the strokes".into());
+            }
+
+            scratch.apply_change(*layer, change, false);
+        }
+        scratch.render_layers();
+
+        // every step gets done from the one before it, going back up the tree and
+        // down another branch with the pixels that were already worked out
+        let mut actions: Vec<(Option<usize>, DrawChangeAction)> = Vec::new();
+        let mut at = None;
+        for (position, (parent, layer, change)) in steps.into_iter().enumerate() {
+            if parent.is_some_and(|p| p >= position) {
+                return Err("the history has a step before the one it was done after".into());
+            }
 
-        // 16x16 being the pixel size of the number
-        let x_start = size.width - offset.x - 16;
-        let y_start = size.height - offset.y - 16;
+            let mut path = Vec::new();
+            let mut id = parent;
+            while let Some(step) = id {
+                path.push(step);
+                id = actions[step].0;
+            }
 
-        let mut coords = Vec::new();
+            while let Some(step) = at.filter(|a| !path.contains(a)) {
+                scratch.apply_action(&actions[step].1, false);
+                at = actions[step].0;
+            }
 
-        get_art(num).iter().enumerate().for_each(|(i, v)| {
-            let y_offset = (i / 16) as u32;
+            let common = at.map_or(path.len(), |a| path.iter().position(|p| *p == a).unwrap());
+            for &step in path[..common].iter().rev() {
+                scratch.apply_action(&actions[step].1, true);
+            }
 
-            if v == &1 {
-                coords.push((
-                    (x_start + (i as u32) % 16) as i32,
-                    (y_start + y_offset) as i32,
-                ));
+            if !scratch.step_fits(layer, &change, true) {
+                return Err("the history doesnt match the strokes".into());
             }
-        });
 
-        let pixels = self.bulk_pixel_convert(coords);
+            let pixels = match change.changes_layers() {
+                true => {
+                    scratch.apply_layer_change(layer, &change, true);
+                    PixelDiff::default()
+                }
+                false => scratch.on_layer(layer, |canvas| {
+                    let before = canvas.pixels().to_vec();
+                    canvas.apply_stroke_change(&change, true);
+                    canvas.rerender();
 
-        let mut buffer = self
-            .surface
-            .buffer_mut()
-            .expect("hopefully this doesnt fail ever !");
-        for px in pixels {
-            // dbg!(px);
-            buffer[px as usize] = self.brush_color;
+                    PixelDiff::between(&before, canvas.pixels())
+                }),
+            };
+
+            let stroke = change;
+            actions.push((
+                parent,
+                DrawChangeAction {
+                    layer,
+                    pixels,
+                    stroke,
+                },
+            ));
+            at = Some(position);
+        }
+
+        let history = UndoTree::from_flat(FlatTree {
+            steps: actions,
+            done,
+            line,
+        })?;
+
+        self.replace_layers(layers, active);
+        self.history = history;
+        self.temp_stack.clear();
+        self.trim_history();
+
+        let settings = session.settings;
+        self.brush_color = settings.brush_color;
+        self.brush_size = settings.brush_size.clamp(1, 10);
+        self.brush_edge = settings.brush_edge;
+        self.tool = settings.tool;
+        self.erase_mode = settings.erase_mode;
+        self.background = settings.background;
+
+        // strokes are kept as they were drawn, so they just run off the edge of a smaller screen
+        if size != self.canvas_size {
+            self.show_notice(
+                format!(
+                    "this drawing was made at {}x{}, its shown at {}x{}",
+                    size.width, size.height, self.canvas_size.width, self.canvas_size.height
+                ),
+                false,
+            );
         }
 
         Ok(())
     }
 
-    pub fn to_pixel_pos(&self, location: PhysicalPosition<f64>) -> u32 {
-        let x = (location.x as u32).clamp(0, self.canvas_size.width - 1);
-        let y = (location.y as u32).clamp(0, self.canvas_size.height - 1);
+    /// Swaps every layer out for `layers` and renders them
+    fn replace_layers(&mut self, layers: Vec<(LayerProps, Vec<Stroke>)>, active: usize) {
+        self.layers = (layers.into_iter())
+            .map(|(props, strokes)| Layer {
+                strokes,
+                ..Layer::new(props, self.canvas_size)
+            })
+            .collect();
+
+        // the active layer keeps the target the canvas was made with
+        self.active_layer = active;
+        let layer = &mut self.layers[active];
+        self.strokes = std::mem::take(&mut layer.strokes);
+        layer.target = Box::new(PixelBuffer::new(PhysicalSize::new(0, 0), CLEAR_BG_COLOR));
 
-        x + (y * self.canvas_size.width)
+        self.render_layers();
+        self.selected = None;
     }
 
-    pub fn bulk_pixel_convert(&self, v: Vec<(i32, i32)>) -> Vec<u32> {
-        v.iter()
-            .map(|(x, y)| {
-                let x = (*x as u32).clamp(0, self.canvas_size.width - 1);
-                let y = (*y as u32).clamp(0, self.canvas_size.height - 1);
+    /// Re-renders every layer, not just the one being drawn on
+    fn render_layers(&mut self) {
+        self.rerender();
 
-                x + (y * self.canvas_size.width)
-            })
-            .collect::<Vec<u32>>()
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            if i != self.active_layer {
+                render_strokes(layer.strokes.iter(), layer.target.as_mut(), 1.0);
+            }
+        }
+    }
+
+    /// Strokes on layer `index`, wherever theyre kept right now
+    fn layer_strokes(&self, index: usize) -> &[Stroke] {
+        match index == self.active_layer {
+            true => &self.strokes,
+            false => &self.layers[index].strokes,
+        }
+    }
+
+    fn layer_pixels(&self, index: usize) -> &[u32] {
+        match index == self.active_layer {
+            true => self.target.pixels(),
+            false => self.layers[index].target.pixels(),
+        }
+    }
+
+    /// Settings of the layer being drawn on
+    pub fn layer(&self) -> &LayerProps {
+        &self.layers[self.active_layer].props
+    }
+
+    pub fn layer_mut(&mut self) -> &mut LayerProps {
+        &mut self.layers[self.active_layer].props
+    }
+
+    /// Something like "layer 2 (2 of 3), 50%, hidden" for showing after the layers changed
+    pub fn layer_status(&self) -> String {
+        let props = self.layer();
+        let mut status = format!(
+            "{} ({} of {})",
+            props.name,
+            self.active_layer + 1,
+            self.layers.len()
+        );
+
+        if props.opacity < 1.0 {
+            status += &format!(", {}%", (props.opacity * 100.0).round());
+        }
+        if !props.visible {
+            status += ", hidden";
+        }
+        if props.locked {
+            status += ", locked";
+        }
+
+        status
+    }
+
+    /// Whether the layer being drawn on can be changed, shows why not when it cant
+    fn layer_editable(&mut self) -> bool {
+        let props = self.layer();
+
+        let reason = match (props.locked, props.visible) {
+            (true, _) => "locked",
+            (false, false) => "hidden",
+            (false, true) => return true,
+        };
+
+        let notice = format!("{} is {reason}", props.name);
+        self.show_notice(notice, true);
+
+        false
+    }
+
+    pub fn change_layer_opacity(&mut self, d: f32) {
+        let props = self.layer_mut();
+
+        // rounded so stepping up and down lands on the same values every time
+        let opacity = ((props.opacity + d) / OPACITY_STEP).round() * OPACITY_STEP;
+        props.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    /// Moves the strokes and pixels of the layer being drawn on back into its spot in `layers`, or out of it again
+    fn swap_active(&mut self) {
+        let layer = &mut self.layers[self.active_layer];
+
+        std::mem::swap(&mut self.strokes, &mut layer.strokes);
+        std::mem::swap(&mut self.target, &mut layer.target);
+    }
+
+    /// Picks the layer thats drawn on, false when there isnt one at `index`
+    pub fn select_layer(&mut self, index: usize) -> bool {
+        if self.drawing || index >= self.layers.len() {
+            return false;
+        }
+
+        self.swap_active();
+        self.active_layer = index;
+        self.swap_active();
+
+        self.selected = None;
+        true
+    }
+
+    /// Runs `f` as if layer `index` was being drawn on
+    fn on_layer<R>(&mut self, index: usize, f: impl FnOnce(&mut Self) -> R) -> R {
+        let active = self.active_layer;
+        if index == active {
+            return f(self);
+        }
+
+        self.swap_active();
+        self.active_layer = index;
+        self.swap_active();
+
+        let result = f(self);
+
+        self.swap_active();
+        self.active_layer = active;
+        self.swap_active();
+
+        result
+    }
+
+    /// Adds an empty layer above the one being drawn on and switches to it
+    pub fn add_layer(&mut self) {
+        if self.drawing {
+            return;
+        }
+
+        let index = self.active_layer + 1;
+        let props = LayerProps::named(format!("layer {}", self.layers.len() + 1));
+        let change = StrokeChange::AddedLayer(props);
+
+        self.apply_layer_change(index, &change, true);
+        self.push_action(DrawChangeAction {
+            layer: index,
+            pixels: PixelDiff::default(),
+            stroke: change,
+        });
+
+        self.select_layer(index);
+    }
+
+    /// Puts the layer being drawn on on top of the one under it, as a single undoable step
+    pub fn merge_layer_down(&mut self) -> Result<(), Box<dyn Error>> {
+        let index = self.active_layer;
+
+        if self.drawing {
+            return Err("cant merge while drawing".into());
+        }
+        if index == 0 {
+            return Err("theres no layer under this one".into());
+        }
+
+        let (props, below) = (self.layer().clone(), &self.layers[index - 1].props);
+        if props.locked || below.locked {
+            return Err("one of the layers is locked".into());
+        }
+        if !props.visible || !below.visible {
+            return Err("one of the layers is hidden".into());
+        }
+        // a faded layer is faded as a whole, fading every stroke on their own
+        // would make the spots where they overlap darker than they were
+        if props.opacity < 1.0 || below.opacity < 1.0 {
+            return Err("one of the layers isnt fully opaque".into());
+        }
+        // they only ever erased this layer, on the one under it theyd erase that too
+        if self.strokes.iter().any(|s| s.tool == Tool::Eraser) {
+            return Err("the layer has eraser strokes on it".into());
+        }
+
+        let change = StrokeChange::MergedLayer {
+            props,
+            strokes: self.strokes.clone(),
+        };
+
+        self.apply_layer_change(index, &change, true);
+        self.push_action(DrawChangeAction {
+            layer: index,
+            pixels: PixelDiff::default(),
+            stroke: change,
+        });
+
+        Ok(())
     }
 
-    pub fn get_line_points(
-        &self,
-        prev: PhysicalPosition<f64>,
-        curr: PhysicalPosition<f64>,
-    ) -> Vec<(i32, i32)> {
-        let mut points: Vec<(i32, i32)> = vec![];
+    /// Adds or merges away layer `index`. Layers are rendered again from their strokes instead of
+    /// keeping their pixels in the history, this doesnt happen nearly as often as drawing
+    fn apply_layer_change(&mut self, index: usize, change: &StrokeChange, redo: bool) {
+        // every layer holds its own strokes and pixels while theyre moved around
+        self.swap_active();
+
+        let size = self.canvas_size;
+        let render = |layer: &mut Layer| {
+            render_strokes(layer.strokes.iter(), layer.target.as_mut(), 1.0);
+        };
 
-        let (x1, y1) = (prev.x as i32, prev.y as i32);
-        let (x2, y2) = (curr.x as i32, curr.y as i32);
+        match (change, redo) {
+            (StrokeChange::AddedLayer(props), true) => {
+                self.layers.insert(index, Layer::new(props.clone(), size));
 
-        let dx = (x2 - x1).abs();
-        let dy = (y2 - y1).abs();
-        let sx = if x1 < x2 { 1 } else { -1 };
-        let sy = if y1 < y2 { 1 } else { -1 };
+                if self.active_layer >= index {
+                    self.active_layer += 1;
+                }
+            }
+            (StrokeChange::AddedLayer(_), false) => {
+                self.layers.remove(index);
 
-        let mut err = dx - dy;
-        let mut x = x1;
-        let mut y = y1;
+                // back to the one under it, which is where the layer was added from
+                if self.active_layer > index || (self.active_layer == index && index > 0) {
+                    self.active_layer -= 1;
+                }
+            }
+            (StrokeChange::MergedLayer { strokes, .. }, true) => {
+                self.layers.remove(index);
 
-        loop {
-            points.push((x, y));
+                let below = &mut self.layers[index - 1];
+                below.strokes.extend(strokes.iter().cloned());
+                render(below);
 
-            if x == x2 && y == y2 {
-                break;
+                if self.active_layer >= index {
+                    self.active_layer -= 1;
+                }
+            }
+            (StrokeChange::MergedLayer { props, strokes }, false) => {
+                let below = &mut self.layers[index - 1];
+                below.strokes.truncate(below.strokes.len() - strokes.len());
+                render(below);
+
+                let mut layer = Layer::new(props.clone(), size);
+                layer.strokes = strokes.clone();
+                render(&mut layer);
+                self.layers.insert(index, layer);
+
+                if self.active_layer >= index {
+                    self.active_layer += 1;
+                }
             }
+            _ => unreachable!("only layer changes go through here"),
+        }
+
+        self.swap_active();
+        self.selected = None;
+    }
 
-            let e2 = 2 * err;
-            if e2 > -dy {
-                err -= dy;
-                x += sx;
+    /// Whether a step can be applied to the layers as they are now, loaded history cant be trusted
+    fn step_fits(&self, layer: usize, change: &StrokeChange, redo: bool) -> bool {
+        let count = self.layers.len();
+
+        match (change, redo) {
+            (StrokeChange::AddedLayer(_), true) => layer <= count,
+            (StrokeChange::AddedLayer(_), false) => {
+                count > 1 && layer < count && self.layer_strokes(layer).is_empty()
+            }
+            (StrokeChange::MergedLayer { strokes, .. }, true) => {
+                layer > 0 && layer < count && self.layer_strokes(layer).len() == strokes.len()
+            }
+            (StrokeChange::MergedLayer { strokes, .. }, false) => {
+                layer > 0 && layer <= count && self.layer_strokes(layer - 1).len() >= strokes.len()
             }
-            if e2 < dx {
-                err += dx;
-                y += sy;
+            (change, redo) => {
+                layer < count && change_fits(change, self.layer_strokes(layer).len(), redo)
             }
         }
+    }
+
+    /// Throws away the pixel buffer and draws every stroke again
+    pub fn rerender(&mut self) {
+        render_strokes(self.strokes.iter(), self.target.as_mut(), 1.0);
+    }
+
+    /// Topmost stroke under `location`
+    pub fn stroke_at(&self, location: PhysicalPosition<f64>) -> Option<usize> {
+        self.strokes
+            .iter()
+            .rposition(|stroke| stroke.tool != Tool::Eraser && stroke.hit_test(location, 2.0))
+    }
+
+    pub fn select_at(&mut self, location: PhysicalPosition<f64>) -> Option<usize> {
+        self.selected = self.stroke_at(location);
+        self.selected
+    }
+
+    pub fn move_selected(&mut self, dx: f64, dy: f64) {
+        if let Some(index) = self.selected {
+            self.edit_stroke(index, |stroke| stroke.translate(dx, dy));
+        }
+    }
+
+    pub fn recolor_selected(&mut self, color: u32) {
+        if let Some(index) = self.selected {
+            self.edit_stroke(index, |stroke| stroke.color = color);
+        }
+    }
+
+    /// Changes a committed stroke and re-renders, recorded as a single undoable action
+    pub fn edit_stroke(&mut self, index: usize, edit: impl FnOnce(&mut Stroke)) {
+        if index >= self.strokes.len() || !self.layer_editable() {
+            return;
+        }
+
+        let stroke = &mut self.strokes[index];
+
+        let before = stroke.clone();
+        edit(stroke);
+        let after = stroke.clone();
+
+        let old_pixels = self.target.pixels().to_vec();
+        self.rerender();
+
+        let pixels = PixelDiff::between(&old_pixels, self.target.pixels());
+
+        self.push_action(DrawChangeAction {
+            layer: self.active_layer,
+            pixels,
+            stroke: StrokeChange::Edited {
+                index,
+                before,
+                after,
+            },
+        });
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.step(false)
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.step(true)
+    }
+
+    /// Swaps the last step for another one that was done from the same place.
+    /// Returns which branch that is counting from 1 and how many there are, None when theres nothing to switch to
+    pub fn switch_branch(&mut self, step: isize) -> Option<(usize, usize)> {
+        if self.drawing || self.history.branch().1 < 2 {
+            return None;
+        }
+
+        self.undo();
+        self.history.cycle_redo(step);
+        self.redo();
 
-        points
+        Some(self.history.branch())
     }
 
-    pub fn get_circle_points(&self, location: PhysicalPosition<f64>) -> Vec<(i32, i32)> {
-        let radius = self.brush_size as i32 - 1;
-        let mut points = Vec::<(i32, i32)>::new();
+    /// Undoes or redoes whatever step the history hands back, false when there wasnt one
+    fn step(&mut self, redo: bool) -> bool {
+        // the stroke being drawn is on the layer it started on
+        if self.drawing {
+            return false;
+        }
 
-        let x_center = location.x as i32;
-        let y_center = location.y as i32;
+        // taken out for a moment so the step can be applied to the rest of the canvas
+        let mut history = std::mem::take(&mut self.history);
 
-        // i still want to draw 1 pixel sometimes
-        if self.brush_size == 1 {
-            return vec![(x_center, y_center)];
+        let action = match redo {
+            true => history.redo(),
+            false => history.undo(),
         };
+        let stepped = action.is_some();
+
+        if let Some(action) = action {
+            self.apply_action(action, redo);
+        }
+
+        self.history = history;
+        stepped
+    }
 
-        // Thanks stackoverflow, i dont like math.
-        // Very Bad "Algorithm"
-        for y in -radius..=radius {
-            for x in -radius..=radius {
-                if x * x + y * y < radius * radius + radius {
-                    points.push((x_center + x, y_center + y));
+    /// Puts a recorded step back on whichever layer it was made on, or takes it off again
+    fn apply_action(&mut self, action: &DrawChangeAction, redo: bool) {
+        if action.stroke.changes_layers() {
+            self.apply_layer_change(action.layer, &action.stroke, redo);
+            return;
+        }
+
+        self.on_layer(action.layer, |canvas| {
+            action.pixels.apply(canvas.target.pixels_mut(), redo);
+            canvas.apply_stroke_change(&action.stroke, redo);
+        });
+    }
+
+    /// Same as `apply_action` without touching the pixels, for history that hasnt been rendered yet
+    fn apply_change(&mut self, layer: usize, change: &StrokeChange, redo: bool) {
+        match change.changes_layers() {
+            true => self.apply_layer_change(layer, change, redo),
+            false => self.on_layer(layer, |canvas| canvas.apply_stroke_change(change, redo)),
+        }
+    }
+
+    /// Adds a step to the undo history, dropping old ones if that takes it over `history_limit`.
+    /// Anything that was undone stays around as a branch
+    fn push_action(&mut self, action: DrawChangeAction) {
+        self.history.push(action);
+        self.trim_history();
+    }
+
+    pub fn trim_history(&mut self) {
+        self.history
+            .trim(self.history_limit, DrawChangeAction::bytes);
+    }
+
+    fn apply_stroke_change(&mut self, change: &StrokeChange, t: bool) {
+        match (change, t) {
+            (StrokeChange::Added(index, stroke), true) => {
+                self.strokes.insert(*index, stroke.clone());
+            }
+            (StrokeChange::Added(index, _), false) => {
+                self.strokes.remove(*index);
+            }
+            (StrokeChange::Edited { index, after, .. }, true) => {
+                self.strokes[*index] = after.clone();
+            }
+            (StrokeChange::Edited { index, before, .. }, false) => {
+                self.strokes[*index] = before.clone();
+            }
+            (StrokeChange::Removed(removed), true) => {
+                for (index, _) in removed.iter().rev() {
+                    self.strokes.remove(*index);
                 }
             }
+            (StrokeChange::Removed(removed), false) => {
+                for (index, stroke) in removed {
+                    self.strokes.insert(*index, stroke.clone());
+                }
+            }
+            (StrokeChange::Pasted(pasted), true) => {
+                self.strokes.extend(pasted.iter().cloned());
+            }
+            (StrokeChange::Pasted(pasted), false) => {
+                self.strokes.truncate(self.strokes.len() - pasted.len());
+            }
+            (StrokeChange::Replaced { after, .. }, true) => {
+                self.strokes = after.clone();
+            }
+            (StrokeChange::Replaced { before, .. }, false) => {
+                self.strokes = before.clone();
+            }
+            (StrokeChange::AddedLayer(_) | StrokeChange::MergedLayer { .. }, _) => {
+                unreachable!("layer changes go through apply_layer_change")
+            }
         }
 
-        // sort vec and then call dedup, which removes all consecutive repeating values :D
-        points.sort();
-        points.dedup();
-        points
+        self.selected = None;
+    }
+
+    pub fn change_brush_size(&mut self, d: i32) {
+        let original = self.brush_size;
+
+        let n = (self.brush_size as i32 + d).clamp(1, 10) as u32;
+
+        // technically this would also trigger even if the original is the same, but its been 5 seconds so its whatever
+        if self.brush_changed_at.elapsed() < Duration::new(1, 0) || original == n {
+            return;
+        };
+
+        self.brush_size = n;
+        self.brush_number = Some((n, Instant::now()));
     }
 
     pub fn draw(
         &mut self,
         location: PhysicalPosition<f64>,
-        prev_location: PhysicalPosition<f64>,
+        _prev_location: PhysicalPosition<f64>,
     ) -> Result<(), Box<dyn Error>> {
-        let line_points = self.get_line_points(prev_location, location);
+        // nothing to draw into outside of a stroke
+        let Some((stroke, _)) = self.current_stroke.as_ref() else {
+            return Ok(());
+        };
+
+        if stroke.tool.is_shape() {
+            self.update_shape(location);
+
+            return Ok(());
+        }
+
+        // while stroking the smoother decides where the ink goes, so prev_location isnt needed
+        for p in self.smoother.feed(location) {
+            self.extend_stroke(p);
+        }
+
+        Ok(())
+    }
+
+    fn extend_stroke(&mut self, location: PhysicalPosition<f64>) {
+        let Some((mut stroke, started)) = self.current_stroke.take() else {
+            return;
+        };
+
+        stroke.push(location, started.elapsed(), &self.pen);
+
+        if self.stroke_erase.is_some() {
+            self.current_stroke = Some((stroke, started));
+            self.erase_strokes_at(location);
+            return;
+        }
+
+        let point = *stroke.points.last().unwrap();
+        let prev = self.last_point.unwrap_or(point);
+        self.last_point = Some(point);
+
+        self.ink(&stroke, stroke.segment_coverage(&prev, &point, 1.0));
+        self.current_stroke = Some((stroke, started));
+    }
 
-        dbg!(&self.action_stack.len());
+    /// Redraws the shape being dragged out, the previous preview is rolled back first
+    /// so the whole drag ends up as a single change
+    fn update_shape(&mut self, cursor: PhysicalPosition<f64>) {
+        let Some((mut stroke, started)) = self.current_stroke.take() else {
+            return;
+        };
 
-        let mut points: Vec<u32> = Vec::new();
+        let (anchor, _) = *self.shape_drag.get_or_insert((cursor, cursor));
+        self.shape_drag = Some((anchor, cursor));
+
+        let buffer = self.target.pixels_mut();
+        for change in self.temp_stack.drain(..).rev() {
+            buffer[change.buf_index as usize] = change.before_data;
+        }
+        self.stroke_coverage.clear();
+
+        let (start, end) = shapes::constrain(
+            stroke.tool,
+            anchor,
+            cursor,
+            self.constrain_shape,
+            self.shape_from_center,
+        );
+
+        stroke.points.clear();
+        stroke.push(start, started.elapsed(), &self.pen);
+        stroke.push(end, started.elapsed(), &self.pen);
+
+        let path = stroke.path();
+        let coverage = match path.as_ref() {
+            [] => Vec::new(),
+            [p] => stroke.segment_coverage(p, p, 1.0),
+            all => all
+                .windows(2)
+                .flat_map(|w| stroke.segment_coverage(&w[0], &w[1], 1.0))
+                .collect(),
+        };
+
+        self.ink(&stroke, coverage);
+        self.current_stroke = Some((stroke, started));
+    }
 
-        for p in line_points {
-            points.extend(self.bulk_pixel_convert(self.get_circle_points(p.into())));
+    /// Redraws the shape being dragged, for when the modifiers change without the cursor moving
+    pub fn refresh_shape(&mut self) {
+        if let Some((_, cursor)) = self.shape_drag {
+            self.update_shape(cursor);
         }
+    }
+
+    /// Puts `coverage` of `stroke` into the buffer, blending against what was there before the stroke
+    fn ink(&mut self, stroke: &Stroke, coverage: Vec<((i32, i32), f32)>) {
+        let size = self.canvas_size;
+        let buffer = self.target.pixels_mut();
+        for ((x, y), coverage) in coverage {
+            if x < 0 || y < 0 || x as u32 >= size.width || y as u32 >= size.height {
+                continue;
+            }
+
+            let px = x as u32 + y as u32 * size.width;
+            let (original, covered) = self
+                .stroke_coverage
+                .entry(px)
+                .or_insert((buffer[px as usize], 0.0));
+
+            // blending again on top of our own ink would darken the joints between segments,
+            // so always blend against what was there before the stroke started
+            if coverage <= *covered {
+                continue;
+            }
+            *covered = coverage;
 
-        let mut buffer = self.surface.buffer_mut()?;
-        for px in points {
-            // dbg!(px);
+            let after = stroke.composite(*original, coverage);
             self.temp_stack.push(PixelChange {
                 buf_index: px,
                 before_data: buffer[px as usize],
-                after_data: self.brush_color,
+                after_data: after,
             });
 
-            buffer[px as usize] = self.brush_color;
+            buffer[px as usize] = after;
         }
+    }
+}
 
-        Ok(())
+/// Whether `change` can be applied to a list of `len` strokes, loaded history cant be trusted
+fn change_fits(change: &StrokeChange, len: usize, redo: bool) -> bool {
+    match (change, redo) {
+        (StrokeChange::Added(index, _), true) => *index <= len,
+        (StrokeChange::Added(index, _), false) => *index < len,
+        (StrokeChange::Edited { index, .. }, _) => *index < len,
+        (StrokeChange::Removed(removed), _) => {
+            let sorted = removed.windows(2).all(|w| w[0].0 < w[1].0);
+
+            // redo takes them out of the current list, undo puts them back in between the others
+            let in_range = removed.last().is_none_or(|(last, _)| match redo {
+                true => *last < len,
+                false => *last < len + removed.len(),
+            });
+
+            sorted && in_range
+        }
+        (StrokeChange::Pasted(pasted), redo) => redo || pasted.len() <= len,
+        (StrokeChange::Replaced { before, after }, redo) => match redo {
+            true => before.len() == len,
+            false => after.len() == len,
+        },
+        // these are checked against the layers in `step_fits`
+        (StrokeChange::AddedLayer(_) | StrokeChange::MergedLayer { .. }, _) => false,
+    }
+}
+
+fn render_strokes<'a>(
+    strokes: impl Iterator<Item = &'a Stroke>,
+    target: &mut dyn PixelTarget,
+    scale: f64,
+) {
+    let size = target.size();
+    let buffer = target.pixels_mut();
+    buffer.fill(CLEAR_BG_COLOR);
+
+    for stroke in strokes {
+        // a fill is one rectangle, going through it pixel by pixel is fine but a map of them isnt
+        if let Some(((x_min, y_min), (x_max, y_max))) = stroke.fill_area(scale) {
+            let clamp = |v: i32, len: u32| v.clamp(0, len as i32) as usize;
+            let (x_min, x_max) = (clamp(x_min, size.width), clamp(x_max + 1, size.width));
+            let (y_min, y_max) = (clamp(y_min, size.height), clamp(y_max + 1, size.height));
+
+            for row in buffer
+                .chunks_mut(size.width.max(1) as usize)
+                .take(y_max)
+                .skip(y_min)
+            {
+                for px in &mut row[x_min..x_max] {
+                    *px = stroke.composite(*px, 1.0);
+                }
+            }
+
+            continue;
+        }
+
+        for ((x, y), coverage) in stroke.coverage(scale) {
+            if x < 0 || y < 0 || x as u32 >= size.width || y as u32 >= size.height {
+                continue;
+            }
+
+            let px = (x as u32 + y as u32 * size.width) as usize;
+            buffer[px] = stroke.composite(buffer[px], coverage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drags the brush through `points` like the mouse would
+    fn stroke(canvas: &mut Canvas, points: &[(f64, f64)]) {
+        canvas.invert_drawing();
+        for &(x, y) in points {
+            let p = PhysicalPosition::new(x, y);
+            canvas.draw(p, p).unwrap();
+        }
+        canvas.invert_drawing();
+    }
+
+    /// Two layers with translucent smooth strokes crossing each other on both
+    fn layered() -> Canvas {
+        let mut canvas = Canvas::headless(PhysicalSize::new(48, 48));
+        canvas.brush_edge = BrushEdge::Smooth;
+        canvas.brush_size = 4;
+
+        canvas.brush_color = 0x80ff5555;
+        stroke(&mut canvas, &[(4.0, 4.0), (44.0, 44.0)]);
+        stroke(&mut canvas, &[(44.0, 4.0), (4.0, 44.0)]);
+
+        canvas.add_layer();
+        canvas.brush_color = 0xc05599ff;
+        stroke(&mut canvas, &[(24.0, 2.0), (24.0, 46.0)]);
+        stroke(&mut canvas, &[(2.0, 24.0), (46.0, 24.0)]);
+
+        canvas
+    }
+
+    /// Every blend rounds to 8 bits, so strokes drawn straight onto the layer under them
+    /// can come out 1 off from the same strokes put on top of it as a layer
+    fn assert_looks_the_same(a: &[u32], b: &[u32]) {
+        assert_eq!(a.len(), b.len());
+
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            let close = [24, 16, 8, 0]
+                .iter()
+                .all(|shift| ((a >> shift) & 0xff).abs_diff((b >> shift) & 0xff) <= 1);
+
+            assert!(close, "pixel {i} went from {a:08x} to {b:08x}");
+        }
+    }
+
+    fn is_blank(canvas: &Canvas) -> bool {
+        canvas
+            .target
+            .pixels()
+            .iter()
+            .all(|px| *px == CLEAR_BG_COLOR)
+    }
+
+    #[test]
+    fn drawing_undoes_and_redoes() {
+        let mut canvas = Canvas::headless(PhysicalSize::new(32, 32));
+        stroke(&mut canvas, &[(4.0, 4.0), (28.0, 20.0)]);
+
+        let drawn = canvas.target.pixels().to_vec();
+        assert!(!is_blank(&canvas));
+
+        assert!(canvas.undo());
+        assert!(is_blank(&canvas));
+        assert!(!canvas.undo());
+
+        assert!(canvas.redo());
+        assert_eq!(canvas.target.pixels(), drawn);
+        assert!(!canvas.redo());
+    }
+
+    #[test]
+    fn filling_covers_the_canvas() {
+        let mut canvas = Canvas::headless(PhysicalSize::new(16, 12));
+        stroke(&mut canvas, &[(2.0, 2.0), (10.0, 8.0)]);
+        let drawn = canvas.target.pixels().to_vec();
+
+        canvas.fill(0xff112233);
+        assert!(canvas.target.pixels().iter().all(|px| *px == 0xff112233));
+
+        assert!(canvas.undo());
+        assert_eq!(canvas.target.pixels(), drawn);
+
+        assert!(canvas.redo());
+        assert!(canvas.target.pixels().iter().all(|px| *px == 0xff112233));
+    }
+
+    #[test]
+    fn brush_size_number_skips_small_canvases() {
+        let mut pixels = vec![CLEAR_BG_COLOR; 64];
+
+        draw_number(&mut pixels, PhysicalSize::new(8, 8), 5, 0xffffffff);
+        assert!(pixels.iter().all(|px| *px == CLEAR_BG_COLOR));
+    }
+
+    #[test]
+    fn picked_strokes_move_and_recolor() {
+        let mut canvas = Canvas::headless(PhysicalSize::new(48, 32));
+        canvas.brush_color = 0xffff0000;
+        stroke(&mut canvas, &[(8.0, 8.0), (16.0, 8.0)]);
+        stroke(&mut canvas, &[(8.0, 24.0), (16.0, 24.0)]);
+        let drawn = canvas.target.pixels().to_vec();
+
+        assert_eq!(canvas.select_at(PhysicalPosition::new(30.0, 16.0)), None);
+        assert_eq!(canvas.select_at(PhysicalPosition::new(12.0, 24.0)), Some(1));
+
+        canvas.move_selected(20.0, 0.0);
+        canvas.recolor_selected(0xff0000ff);
+
+        let at = |canvas: &Canvas, x: u32, y: u32| canvas.target.pixels()[(x + y * 48) as usize];
+        assert_eq!(at(&canvas, 32, 24), 0xff0000ff);
+        assert_eq!(at(&canvas, 12, 24), CLEAR_BG_COLOR);
+        assert_eq!(at(&canvas, 12, 8), 0xffff0000);
+
+        // the move and the recolor are separate steps
+        assert!(canvas.undo());
+        assert_eq!(at(&canvas, 32, 24), 0xffff0000);
+        assert!(canvas.undo());
+        assert_eq!(canvas.target.pixels(), drawn);
+    }
+
+    #[test]
+    fn merging_down_looks_the_same() {
+        let mut canvas = layered();
+        let before = canvas.flattened();
+
+        canvas.merge_layer_down().unwrap();
+        assert_eq!(canvas.layers.len(), 1);
+        assert_looks_the_same(&canvas.flattened(), &before);
+
+        assert!(canvas.undo());
+        assert_eq!(canvas.layers.len(), 2);
+        assert_eq!(canvas.flattened(), before);
+    }
+
+    #[test]
+    fn merging_down_refuses_faded_layers() {
+        let mut canvas = layered();
+        canvas.change_layer_opacity(-0.5);
+        let before = canvas.flattened();
+
+        assert!(canvas.merge_layer_down().is_err());
+        assert_eq!(canvas.layers.len(), 2);
+        assert_eq!(canvas.flattened(), before);
+
+        // the one underneath counts too, its opacity would end up on the merged strokes
+        canvas.change_layer_opacity(0.5);
+        canvas.select_layer(0);
+        canvas.change_layer_opacity(-0.5);
+        canvas.select_layer(1);
+        assert!(canvas.merge_layer_down().is_err());
     }
 }
@@ -0,0 +1,125 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use super::png;
+
+/// Turns premultiplied 0xAARRGGBB pixels into the straight alpha RGBA bytes image formats expect
+pub fn to_rgba(pixels: &[u32]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(pixels.len() * 4);
+
+    for px in pixels {
+        let a = (px >> 24) & 0xff;
+        let channel = |shift: u32| match a {
+            0 => 0,
+            a => ((((px >> shift) & 0xff) * 255 + a / 2) / a).min(0xff) as u8,
+        };
+
+        rgba.extend_from_slice(&[channel(16), channel(8), channel(0), a as u8]);
+    }
+
+    rgba
+}
+
+pub fn encode_png(pixels: &[u32], size: PhysicalSize<u32>) -> Vec<u8> {
+    png::encode(size.width, size.height, &to_rgba(pixels))
+}
+
+pub fn save_png(
+    pixels: &[u32],
+    size: PhysicalSize<u32>,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(path, encode_png(pixels, size))?;
+
+    Ok(())
+}
+
+/// Smallest rectangle holding every pixel that isnt fully transparent, None for an empty canvas
+pub fn ink_bounds(
+    pixels: &[u32],
+    size: PhysicalSize<u32>,
+) -> Option<(PhysicalPosition<u32>, PhysicalSize<u32>)> {
+    let width = size.width.max(1) as usize;
+    let inked = |px: &u32| px >> 24 != 0;
+
+    let first = pixels.iter().position(inked)?;
+    let last = pixels.iter().rposition(inked)?;
+
+    let (mut left, mut right) = (width, 0);
+    for row in pixels[first / width * width..=last].chunks(width) {
+        if let Some(x) = row.iter().position(inked) {
+            left = left.min(x);
+            right = right.max(row.iter().rposition(inked).unwrap());
+        }
+    }
+
+    let (top, bottom) = (first / width, last / width);
+
+    Some((
+        PhysicalPosition::new(left as u32, top as u32),
+        PhysicalSize::new((right - left + 1) as u32, (bottom - top + 1) as u32),
+    ))
+}
+
+/// The `crop` sized piece of `pixels` starting at `origin`, which has to fit inside `size`
+pub fn crop(
+    pixels: &[u32],
+    size: PhysicalSize<u32>,
+    origin: PhysicalPosition<u32>,
+    crop: PhysicalSize<u32>,
+) -> Vec<u32> {
+    let (x, w) = (origin.x as usize, crop.width as usize);
+
+    pixels
+        .chunks(size.width.max(1) as usize)
+        .skip(origin.y as usize)
+        .take(crop.height as usize)
+        .flat_map(|row| &row[x..x + w])
+        .copied()
+        .collect()
+}
+
+/// `dir/draw-2024-05-12_18-30-02.ext`, in UTC. A number gets added if that file already exists
+pub fn timestamped_path(dir: &Path, ext: &str) -> PathBuf {
+    let stamp = timestamp(SystemTime::now());
+
+    (0..)
+        .map(|n| match n {
+            0 => dir.join(format!("draw-{stamp}.{ext}")),
+            n => dir.join(format!("draw-{stamp}-{n}.{ext}")),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // days since 1970 to a calendar date, from Howard Hinnant's civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
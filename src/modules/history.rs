@@ -0,0 +1,521 @@
+// how the pixel side of the undo history is stored. a stroke changes a lot of neighbouring pixels
+// to the same color, so instead of an (index, before, after) for every single pixel the changes are
+// kept as spans of consecutive pixels, with the before and after colors run length encoded.
+// the steps themselves are kept in a tree, so undoing and then drawing something else doesnt lose anything.
+// this doesnt use anything else from the crate so the benchmarks can pull it in on its own
+
+use std::{collections::BTreeMap, mem::size_of};
+
+/// A single pixel that changed, what strokes record while theyre being drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelChange {
+    pub buf_index: u32,
+    pub before_data: u32,
+    pub after_data: u32,
+}
+
+/// `len` pixels in a row that are all `color`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Run {
+    len: u32,
+    color: u32,
+}
+
+/// Every pixel a single action changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PixelDiff {
+    /// First index and length of every stretch of changed pixels, in index order
+    spans: Vec<(u32, u32)>,
+    /// Colors of all the spans one after another
+    before: Vec<Run>,
+    after: Vec<Run>,
+}
+
+impl PixelDiff {
+    /// `changes` have to be sorted by index, with every index only once
+    pub fn from_changes(changes: impl IntoIterator<Item = PixelChange>) -> Self {
+        let mut diff = Self::default();
+
+        for change in changes {
+            match diff.spans.last_mut() {
+                Some((start, len)) if *start + *len == change.buf_index => *len += 1,
+                _ => diff.spans.push((change.buf_index, 1)),
+            }
+
+            push_run(&mut diff.before, change.before_data);
+            push_run(&mut diff.after, change.after_data);
+        }
+
+        // these stick around for a long time, dont keep the growth room
+        diff.spans.shrink_to_fit();
+        diff.before.shrink_to_fit();
+        diff.after.shrink_to_fit();
+
+        diff
+    }
+
+    /// Every pixel that isnt the same in `old` and `new`
+    pub fn between(old: &[u32], new: &[u32]) -> Self {
+        Self::from_changes(
+            old.iter()
+                .zip(new)
+                .enumerate()
+                .filter(|(_, (before, after))| before != after)
+                .map(|(i, (before, after))| PixelChange {
+                    buf_index: i as u32,
+                    before_data: *before,
+                    after_data: *after,
+                }),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// How many pixels changed
+    pub fn len(&self) -> usize {
+        self.spans.iter().map(|(_, len)| *len as usize).sum()
+    }
+
+    /// Every changed pixel in index order
+    pub fn iter(&self) -> impl Iterator<Item = PixelChange> + '_ {
+        self.spans
+            .iter()
+            .flat_map(|&(start, len)| start..start + len)
+            .zip(colors(&self.before).zip(colors(&self.after)))
+            .map(|(buf_index, (before_data, after_data))| PixelChange {
+                buf_index,
+                before_data,
+                after_data,
+            })
+    }
+
+    /// Puts the after colors into `buffer`, or the before colors when `redo` is false
+    pub fn apply(&self, buffer: &mut [u32], redo: bool) {
+        let mut runs = match redo {
+            true => self.after.iter(),
+            false => self.before.iter(),
+        }
+        .copied();
+        let mut run = Run { len: 0, color: 0 };
+
+        for &(start, len) in &self.spans {
+            let (mut i, end) = (start as usize, (start + len) as usize);
+
+            while i < end {
+                if run.len == 0 {
+                    run = runs
+                        .next()
+                        .expect("pixel diff has fewer colors than pixels");
+                }
+
+                let n = (run.len as usize).min(end - i);
+                buffer[i..i + n].fill(run.color);
+
+                run.len -= n as u32;
+                i += n;
+            }
+        }
+    }
+
+    /// Moves the changes over to a buffer with a different size, anything that ends up outside of it is dropped.
+    /// Sizes are (width, height)
+    pub fn remap(&mut self, old: (u32, u32), new: (u32, u32)) {
+        // rows stay in the same order, so the changes stay sorted
+        *self = Self::from_changes(self.iter().filter_map(|mut change| {
+            let (x, y) = (change.buf_index % old.0, change.buf_index / old.0);
+            if x >= new.0 || y >= new.1 {
+                return None;
+            }
+
+            change.buf_index = x + y * new.0;
+            Some(change)
+        }));
+    }
+
+    /// Roughly how much memory the diff holds on to
+    pub fn bytes(&self) -> usize {
+        size_of::<Self>()
+            + self.spans.capacity() * size_of::<(u32, u32)>()
+            + (self.before.capacity() + self.after.capacity()) * size_of::<Run>()
+    }
+}
+
+fn push_run(runs: &mut Vec<Run>, color: u32) {
+    match runs.last_mut() {
+        Some(run) if run.color == color => run.len += 1,
+        _ => runs.push(Run { len: 1, color }),
+    }
+}
+
+fn colors(runs: &[Run]) -> impl Iterator<Item = u32> + '_ {
+    runs.iter()
+        .flat_map(|run| std::iter::repeat_n(run.color, run.len as usize))
+}
+
+/// Every step that was ever done. Doing something after undoing starts a new branch
+/// instead of throwing away what was undone, so it can still be gone back to later
+#[derive(Debug, Clone)]
+pub struct UndoTree<T> {
+    /// Ids only ever go up, so they double as the order the steps were done in
+    nodes: BTreeMap<usize, Node<T>>,
+    /// Steps done from the very start
+    roots: Vec<usize>,
+    /// Which of the roots redo goes into
+    root_redo: Option<usize>,
+    /// Last step done or redone, None when everything is undone
+    current: Option<usize>,
+    next_id: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    action: T,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// Which child redo goes into, the last one that was done or undone
+    redo: Option<usize>,
+}
+
+/// The whole tree as a list, what gets saved in sessions
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatTree<T> {
+    /// Every step with the position of its parent, parents always come first.
+    /// Starts with the steps leading up to the current one followed by the ones redo would go through
+    pub steps: Vec<(Option<usize>, T)>,
+    /// How many steps at the start have been done
+    pub done: usize,
+    /// How many steps at the start are done or would be redone, everything after is off on other branches
+    pub line: usize,
+}
+
+impl<T> Default for UndoTree<T> {
+    fn default() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            roots: Vec::new(),
+            root_redo: None,
+            current: None,
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> UndoTree<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Adds a step after the current one and makes it the current one
+    pub fn push(&mut self, action: T) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let parent = self.current;
+        self.nodes.insert(
+            id,
+            Node {
+                action,
+                parent,
+                children: Vec::new(),
+                redo: None,
+            },
+        );
+
+        let (children, redo) = self.slot(parent);
+        children.push(id);
+        *redo = Some(id);
+
+        self.current = Some(id);
+    }
+
+    /// Steps back, the returned action is the one to undo
+    pub fn undo(&mut self) -> Option<&T> {
+        let id = self.current?;
+        self.current = self.nodes[&id].parent;
+
+        // undoing always comes back the same way with redo
+        *self.slot(self.current).1 = Some(id);
+
+        Some(&self.nodes[&id].action)
+    }
+
+    /// Steps forward along whichever branch was last used from here
+    pub fn redo(&mut self) -> Option<&T> {
+        let id = self.redo_target()?;
+        self.current = Some(id);
+
+        Some(&self.nodes[&id].action)
+    }
+
+    /// Makes redo go into a different branch, `step` places over from the one it goes into now.
+    /// False when theres only one way to go
+    pub fn cycle_redo(&mut self, step: isize) -> bool {
+        let (children, redo) = self.slot(self.current);
+        if children.len() < 2 {
+            return false;
+        }
+
+        let at = children.iter().position(|c| Some(*c) == *redo).unwrap_or(0);
+        let next = (at as isize + step).rem_euclid(children.len() as isize);
+        *redo = Some(children[next as usize]);
+
+        true
+    }
+
+    /// Which of the branches the current step is on counting from 1, and how many there are at that point
+    pub fn branch(&self) -> (usize, usize) {
+        let Some(id) = self.current else {
+            return (0, 0);
+        };
+
+        let siblings = match self.nodes[&id].parent {
+            Some(parent) => &self.nodes[&parent].children,
+            None => &self.roots,
+        };
+        let at = siblings.iter().position(|s| *s == id).unwrap_or(0);
+
+        (at + 1, siblings.len())
+    }
+
+    /// Every action in the tree, in no particular order
+    pub fn actions(&self) -> impl Iterator<Item = &T> + '_ {
+        self.nodes.values().map(|node| &node.action)
+    }
+
+    pub fn actions_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.nodes.values_mut().map(|node| &mut node.action)
+    }
+
+    /// Drops steps until the ones left add up to at most `limit` bytes. Abandoned branches go first,
+    /// then the oldest steps leading up to the current one. The current step and everything
+    /// redo would go through always stay
+    pub fn trim(&mut self, limit: usize, bytes: impl Fn(&T) -> usize) {
+        let mut total: usize = self.actions().map(&bytes).sum();
+
+        while total > limit {
+            let Some(id) = self.droppable() else {
+                break;
+            };
+
+            total -= bytes(&self.remove(id));
+        }
+    }
+
+    /// Flattens the tree, see `FlatTree`
+    pub fn flatten(&self) -> FlatTree<&T> {
+        let mut flat = FlatTree {
+            steps: Vec::new(),
+            done: 0,
+            line: 0,
+        };
+
+        // depth first going into the redo branch before the others, that puts the
+        // steps up to the current one and the redo ones at the start
+        let mut stack: Vec<(Option<usize>, usize)> = ordered(&self.roots, self.root_redo)
+            .map(|id| (None, id))
+            .collect();
+
+        while let Some((parent, id)) = stack.pop() {
+            let node = &self.nodes[&id];
+            let position = flat.steps.len();
+
+            if parent == flat.line.checked_sub(1) && flat.line == position {
+                flat.line += 1;
+            }
+            if Some(id) == self.current {
+                flat.done = position + 1;
+            }
+
+            flat.steps.push((parent, &node.action));
+            stack.extend(ordered(&node.children, node.redo).map(|child| (Some(position), child)));
+        }
+
+        flat
+    }
+
+    /// Builds a tree back out of a flattened one. Redo goes into the first child of every step
+    pub fn from_flat(flat: FlatTree<T>) -> Result<Self, String> {
+        let mut tree = Self::default();
+
+        for (position, (parent, action)) in flat.steps.into_iter().enumerate() {
+            if parent.is_some_and(|p| p >= position) {
+                return Err(format!(
+                    "step {position} comes before the one it was done after"
+                ));
+            }
+
+            tree.current = parent;
+            tree.push(action);
+
+            // push makes the newest child the redo one, the first one is what should stay
+            let (children, redo) = tree.slot(parent);
+            *redo = children.first().copied();
+        }
+
+        tree.current = match flat.done {
+            0 => None,
+            done if done <= tree.nodes.len() => Some(done - 1),
+            done => {
+                return Err(format!(
+                    "{done} steps done but there are only {}",
+                    tree.len()
+                ))
+            }
+        };
+
+        // the steps up to the current one have to lead into it
+        let mut id = tree.current;
+        while let Some(current) = id {
+            let parent = tree.nodes[&current].parent;
+            *tree.slot(parent).1 = Some(current);
+            id = parent;
+        }
+
+        Ok(tree)
+    }
+
+    fn redo_target(&self) -> Option<usize> {
+        match self.current {
+            Some(id) => self.nodes[&id].redo,
+            None => self.root_redo,
+        }
+    }
+
+    /// The children of `parent` and which of them redo goes into, None being the start
+    fn slot(&mut self, parent: Option<usize>) -> (&mut Vec<usize>, &mut Option<usize>) {
+        match parent {
+            Some(id) => {
+                let node = self.nodes.get_mut(&id).expect("undo tree node is missing");
+                (&mut node.children, &mut node.redo)
+            }
+            None => (&mut self.roots, &mut self.root_redo),
+        }
+    }
+
+    /// The oldest step that can go without losing the current one or what redo goes through
+    fn droppable(&self) -> Option<usize> {
+        let mut kept = Vec::new();
+
+        let mut id = self.current;
+        while let Some(current) = id {
+            kept.push(current);
+            id = self.nodes[&current].parent;
+        }
+
+        let mut id = self.redo_target();
+        while let Some(redo) = id {
+            kept.push(redo);
+            id = self.nodes[&redo].redo;
+        }
+
+        let abandoned = self
+            .nodes
+            .iter()
+            .find(|(id, node)| node.children.is_empty() && !kept.contains(id))
+            .map(|(id, _)| *id);
+
+        // once thats gone the oldest step can go, as long as theres nothing else at the start
+        // that would need it undone to get to
+        abandoned.or(match self.roots[..] {
+            [root] if self.current.is_some_and(|c| c != root) && kept.contains(&root) => Some(root),
+            _ => None,
+        })
+    }
+
+    fn remove(&mut self, id: usize) -> T {
+        let node = self.nodes.remove(&id).expect("undo tree node is missing");
+
+        let (siblings, redo) = self.slot(node.parent);
+        siblings.retain(|s| *s != id);
+        if *redo == Some(id) {
+            *redo = node.redo.or(siblings.last().copied());
+        }
+
+        // only ever happens to the first step, its children become the new start
+        for child in &node.children {
+            self.nodes.get_mut(child).unwrap().parent = node.parent;
+        }
+        self.slot(node.parent).0.extend(&node.children);
+
+        node.action
+    }
+}
+
+/// `ids` with the redo one last, so its the first to come off a stack
+fn ordered(ids: &[usize], redo: Option<usize>) -> impl Iterator<Item = usize> + '_ {
+    let others = ids
+        .iter()
+        .rev()
+        .copied()
+        .filter(move |id| Some(*id) != redo);
+    others.chain(redo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Undoes everything, returning the steps in the order they were undone
+    fn undo_all(tree: &mut UndoTree<u32>) -> Vec<u32> {
+        std::iter::from_fn(|| tree.undo().copied()).collect()
+    }
+
+    #[test]
+    fn pixel_diff_restores_pixels_exactly() {
+        let old: Vec<u32> = (0..64).map(|i| i * 0x01020304).collect();
+        let mut new = old.clone();
+        // a long run of one color, a few lone pixels and the very last one
+        new[3..20].fill(0xffff5555);
+        new[30] = 0x80402010;
+        new[32] = 0;
+        new[63] = 0xffffffff;
+
+        let diff = PixelDiff::between(&old, &new);
+        assert_eq!(diff.len(), 20);
+
+        let mut pixels = new.clone();
+        diff.apply(&mut pixels, false);
+        assert_eq!(pixels, old);
+
+        diff.apply(&mut pixels, true);
+        assert_eq!(pixels, new);
+    }
+
+    #[test]
+    fn trimming_keeps_the_current_step_and_the_ones_before_it() {
+        let mut tree = UndoTree::new();
+        tree.push(1);
+        tree.push(2);
+        tree.push(3);
+
+        // going back and drawing something else leaves 2 and 3 on their own branch
+        tree.undo();
+        tree.undo();
+        tree.push(4);
+        tree.push(5);
+
+        tree.trim(3, |_| 1);
+        let mut left: Vec<u32> = tree.actions().copied().collect();
+        left.sort();
+        assert_eq!(left, [1, 4, 5]);
+        assert_eq!(undo_all(&mut tree.clone()), [5, 4, 1]);
+
+        // with less room the oldest go, but never the current one
+        tree.trim(0, |_| 1);
+        assert_eq!(undo_all(&mut tree), [5]);
+    }
+}
@@ -0,0 +1,84 @@
+// layers are drawn on separately and only put together when the canvas is shown or exported.
+// the layer being drawn on keeps its strokes and pixels in the canvas itself, so none of the
+// drawing code has to know there are layers at all
+
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalSize;
+
+use super::pixels::{erase, over, PixelBuffer, PixelTarget};
+use super::stroke::Stroke;
+
+/// How much raising or lowering the opacity changes it
+pub const OPACITY_STEP: f32 = 0.1;
+
+/// Everything about a layer except whats drawn on it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayerProps {
+    pub name: String,
+    pub visible: bool,
+    /// 0 is see through, 1 is as drawn
+    pub opacity: f32,
+    /// Locked layers cant be drawn on, cleared or merged
+    pub locked: bool,
+}
+
+impl Default for LayerProps {
+    fn default() -> Self {
+        Self::named("layer 1")
+    }
+}
+
+impl LayerProps {
+    pub fn named(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            visible: true,
+            opacity: 1.0,
+            locked: false,
+        }
+    }
+
+    /// Whether any of the layer ends up on screen
+    pub fn shown(&self) -> bool {
+        self.visible && self.opacity > 0.0
+    }
+}
+
+pub struct Layer {
+    pub props: LayerProps,
+    /// Every stroke in draw order, empty while its the layer being drawn on
+    pub strokes: Vec<Stroke>,
+    pub target: Box<dyn PixelTarget>,
+}
+
+impl Layer {
+    pub fn new(props: LayerProps, size: PhysicalSize<u32>) -> Self {
+        Self {
+            props,
+            strokes: Vec::new(),
+            target: Box::new(PixelBuffer::new(size, 0x00000000)),
+        }
+    }
+
+    /// Stands in for the layer being drawn on, its real strokes and pixels are in the canvas
+    pub fn placeholder(props: LayerProps) -> Self {
+        Self::new(props, PhysicalSize::new(0, 0))
+    }
+}
+
+/// Puts the premultiplied `layer` on top of `frame` at `opacity`
+pub fn composite(frame: &mut [u32], layer: &[u32], opacity: f32) {
+    if opacity >= 1.0 {
+        for (dst, src) in frame.iter_mut().zip(layer) {
+            *dst = over(*dst, *src);
+        }
+
+        return;
+    }
+
+    // fading a premultiplied pixel is the same as erasing part of it
+    for (dst, src) in frame.iter_mut().zip(layer) {
+        *dst = over(*dst, erase(*src, 1.0 - opacity));
+    }
+}
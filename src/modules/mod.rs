@@ -1,2 +1,16 @@
+pub mod brush;
 pub mod canvas;
 pub mod dmodifiers;
+pub mod export;
+pub mod history;
+pub mod layer;
+pub mod overlay;
+pub mod pen;
+pub mod pixels;
+pub mod png;
+pub mod presenter;
+pub mod session;
+pub mod shapes;
+pub mod smoothing;
+pub mod stroke;
+pub mod svg;
@@ -0,0 +1,81 @@
+use winit::dpi::PhysicalSize;
+
+use super::pixels::blend_over;
+use crate::art::font::{text_pixels, text_width, GLYPH_HEIGHT};
+use crate::art::numbers::get_art;
+
+const HINT_BG_COLOR: u32 = 0xe0181818;
+const HINT_TEXT_COLOR: u32 = 0xffffffff;
+const NOTICE_ERROR_COLOR: u32 = 0xffff6666;
+
+const HINT_SCALE: u32 = 2;
+const HINT_PADDING: u32 = 8;
+/// How far the box sits from the bottom of the screen
+const HINT_MARGIN: u32 = 40;
+
+/// Numbers are 16x16, this far away from the right and bottom edges
+const NUMBER_SIZE: u32 = 16;
+const NUMBER_MARGIN: (u32, u32) = (20, 100);
+
+/// Draws `text` in a box at the bottom middle of `pixels`, used for hints that shouldnt end up in the drawing
+pub fn draw_hint(pixels: &mut [u32], size: PhysicalSize<u32>, text: &str) {
+    let box_h = GLYPH_HEIGHT * HINT_SCALE + HINT_PADDING * 2;
+    let y = size.height.saturating_sub(box_h + HINT_MARGIN) as i32;
+
+    draw_box(pixels, size, text, y, HINT_TEXT_COLOR);
+}
+
+/// Same as a hint but at the top of the screen, errors get their own color
+pub fn draw_notice(pixels: &mut [u32], size: PhysicalSize<u32>, text: &str, error: bool) {
+    let color = match error {
+        true => NOTICE_ERROR_COLOR,
+        false => HINT_TEXT_COLOR,
+    };
+
+    draw_box(pixels, size, text, HINT_MARGIN as i32, color);
+}
+
+fn draw_box(pixels: &mut [u32], size: PhysicalSize<u32>, text: &str, y: i32, text_color: u32) {
+    let box_w = text_width(text, HINT_SCALE) + HINT_PADDING * 2;
+    let box_h = GLYPH_HEIGHT * HINT_SCALE + HINT_PADDING * 2;
+
+    let x = size.width.saturating_sub(box_w) as i32 / 2;
+
+    let mut put = |px: i32, py: i32, color: u32| {
+        if px < 0 || py < 0 || px as u32 >= size.width || py as u32 >= size.height {
+            return;
+        }
+
+        let i = (px as u32 + py as u32 * size.width) as usize;
+        pixels[i] = blend_over(pixels[i], color, 1.0);
+    };
+
+    for py in y..y + box_h as i32 {
+        for px in x..x + box_w as i32 {
+            put(px, py, HINT_BG_COLOR);
+        }
+    }
+
+    let (tx, ty) = (x + HINT_PADDING as i32, y + HINT_PADDING as i32);
+    for (px, py) in text_pixels(text, tx, ty, HINT_SCALE) {
+        put(px, py, text_color);
+    }
+}
+
+/// Draws `n` near the bottom right corner, like the brush size after it changes.
+/// Screens too small to fit it just dont show it
+pub fn draw_number(pixels: &mut [u32], size: PhysicalSize<u32>, n: u32, color: u32) {
+    let (Some(x), Some(y)) = (
+        size.width.checked_sub(NUMBER_MARGIN.0 + NUMBER_SIZE),
+        size.height.checked_sub(NUMBER_MARGIN.1 + NUMBER_SIZE),
+    ) else {
+        return;
+    };
+
+    for (i, _) in get_art(n).iter().enumerate().filter(|(_, v)| **v == 1) {
+        let (px, py) = (x + i as u32 % NUMBER_SIZE, y + i as u32 / NUMBER_SIZE);
+
+        let i = (px + py * size.width) as usize;
+        pixels[i] = blend_over(pixels[i], color, 1.0);
+    }
+}
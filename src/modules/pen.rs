@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Latest input from the pointer, a mouse always reports full pressure and no tilt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PenState {
+    /// 0..=1
+    pub pressure: f32,
+
+    /// (x, y) tilt in degrees, only known for some tablets
+    pub tilt: Option<(f32, f32)>,
+
+    /// The pen is flipped over and the eraser end is touching
+    pub eraser: bool,
+}
+
+impl Default for PenState {
+    fn default() -> Self {
+        Self {
+            pressure: 1.0,
+            tilt: None,
+            eraser: false,
+        }
+    }
+}
+
+/// How strongly pressure changes a stroke, 0 ignores pressure and 1 scales all the way down to nothing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PressureCurve {
+    pub width: f32,
+    pub opacity: f32,
+}
+
+impl Default for PressureCurve {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            opacity: 0.5,
+        }
+    }
+}
+
+impl PressureCurve {
+    pub fn width_factor(&self, pressure: f32) -> f32 {
+        1.0 - self.width.clamp(0.0, 1.0) * (1.0 - pressure.clamp(0.0, 1.0))
+    }
+
+    pub fn opacity(&self, pressure: f32) -> f32 {
+        1.0 - self.opacity.clamp(0.0, 1.0) * (1.0 - pressure.clamp(0.0, 1.0))
+    }
+}
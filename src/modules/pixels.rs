@@ -0,0 +1,110 @@
+use winit::dpi::PhysicalSize;
+
+/// Anything the canvas can draw into, pixels are stored row major as 0xAARRGGBB
+pub trait PixelTarget {
+    fn size(&self) -> PhysicalSize<u32>;
+
+    fn pixels(&self) -> &[u32];
+
+    fn pixels_mut(&mut self) -> &mut [u32];
+
+    /// Existing pixels stay anchored to the top left corner, anything newly exposed gets `fill`
+    fn resize(&mut self, size: PhysicalSize<u32>, fill: u32);
+}
+
+/// Plain in memory pixel buffer, doesnt need a window so it works for tests and offline rendering
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelBuffer {
+    size: PhysicalSize<u32>,
+    data: Vec<u32>,
+}
+
+impl PixelBuffer {
+    pub fn new(size: PhysicalSize<u32>, color: u32) -> Self {
+        Self {
+            size,
+            data: vec![color; (size.width * size.height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.size.width || y >= self.size.height {
+            return None;
+        }
+
+        Some(self.data[(x + y * self.size.width) as usize])
+    }
+}
+
+impl PixelTarget for PixelBuffer {
+    fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    fn pixels(&self) -> &[u32] {
+        &self.data
+    }
+
+    fn pixels_mut(&mut self) -> &mut [u32] {
+        &mut self.data
+    }
+
+    fn resize(&mut self, size: PhysicalSize<u32>, fill: u32) {
+        if size == self.size {
+            return;
+        }
+
+        let mut data = vec![fill; (size.width * size.height) as usize];
+
+        let copy_w = self.size.width.min(size.width) as usize;
+        let copy_h = self.size.height.min(size.height) as usize;
+
+        for y in 0..copy_h {
+            let old_row = y * self.size.width as usize;
+            let new_row = y * size.width as usize;
+
+            data[new_row..new_row + copy_w].copy_from_slice(&self.data[old_row..old_row + copy_w]);
+        }
+
+        self.size = size;
+        self.data = data;
+    }
+}
+
+/// Composites `color` (straight alpha) over `dst` (premultiplied) with `coverage` of the pixel inked.
+/// The result is premultiplied, which is what a transparent window expects
+pub fn blend_over(dst: u32, color: u32, coverage: f32) -> u32 {
+    let alpha = ((color >> 24) & 0xff) as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+
+    let channel = |shift: u32| {
+        let src = ((color >> shift) & 0xff) as f32 * alpha;
+        let dst = ((dst >> shift) & 0xff) as f32 * (1.0 - alpha);
+
+        ((src + dst).round() as u32).min(0xff) << shift
+    };
+
+    let dst_alpha = ((dst >> 24) & 0xff) as f32 * (1.0 - alpha);
+    let out_alpha = ((alpha * 255.0 + dst_alpha).round() as u32).min(0xff) << 24;
+
+    out_alpha | channel(16) | channel(8) | channel(0)
+}
+
+/// Removes `coverage` of whatever is in `dst`, fully covered pixels end up transparent
+pub fn erase(dst: u32, coverage: f32) -> u32 {
+    let keep = 1.0 - coverage.clamp(0.0, 1.0);
+
+    [24, 16, 8, 0].iter().fold(0, |out, shift| {
+        let channel = ((dst >> shift) & 0xff) as f32 * keep;
+        out | ((channel.round() as u32).min(0xff) << shift)
+    })
+}
+
+/// Puts the premultiplied `src` on top of the premultiplied `dst`
+pub fn over(dst: u32, src: u32) -> u32 {
+    let keep = 1.0 - ((src >> 24) & 0xff) as f32 / 255.0;
+
+    [24, 16, 8, 0].iter().fold(0, |out, shift| {
+        let channel = ((src >> shift) & 0xff) as f32 + ((dst >> shift) & 0xff) as f32 * keep;
+        out | ((channel.round() as u32).min(0xff) << shift)
+    })
+}
@@ -0,0 +1,384 @@
+// minimal png encoder, only what exporting the canvas needs: 8 bit RGBA, no interlacing.
+// the deflate side only looks for repeats of the previous byte or pixel, which is most of a drawing
+// (empty space and flat backgrounds), and writes them with the fixed huffman codes
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Longest match deflate allows
+const MAX_MATCH: usize = 258;
+const MIN_MATCH: usize = 3;
+
+/// Encodes straight alpha RGBA bytes, row major, into a png file
+pub fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), (width * height * 4) as usize);
+
+    let mut out = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut out, b"IHDR", &header);
+
+    // every row starts with its filter type, 0 is none
+    let stride = width as usize * 4;
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for row in rgba.chunks(stride.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    write_chunk(&mut out, b"IDAT", &zlib(&raw));
+    write_chunk(&mut out, b"IEND", &[]);
+
+    out
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb88320,
+                _ => crc >> 1,
+            };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // 5552 is the most bytes that can be summed before b could overflow
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    // deflate, 32k window, no preset dictionary, check bits so the header is a multiple of 31
+    let mut out = vec![0x78, 0x01];
+
+    out.extend(deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    out
+}
+
+/// Writes bits least significant first, like deflate wants
+struct BitWriter {
+    out: Vec<u8>,
+    bit: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bit |= value << self.bits;
+        self.bits += count;
+
+        while self.bits >= 8 {
+            self.out.push(self.bit as u8);
+            self.bit >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    /// Huffman codes go in most significant bit first
+    fn write_code(&mut self, code: u32, count: u32) {
+        let reversed = code.reverse_bits() >> (32 - count);
+        self.write(reversed, count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.bit as u8);
+        }
+
+        self.out
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter {
+        out: Vec::with_capacity(data.len() / 4),
+        bit: 0,
+        bits: 0,
+    };
+
+    // a single final block with the fixed codes
+    w.write(1, 1);
+    w.write(1, 2);
+
+    let mut i = 0;
+    while i < data.len() {
+        // repeats of the last byte, or of the last pixel
+        let best = [1, 4]
+            .into_iter()
+            .filter(|distance| i >= *distance)
+            .map(|distance| (distance, match_length(data, i, distance)))
+            .max_by_key(|(_, length)| *length);
+
+        match best {
+            Some((distance, length)) if length >= MIN_MATCH => {
+                write_length(&mut w, length);
+                write_distance(&mut w, distance);
+                i += length;
+            }
+            _ => {
+                write_literal(&mut w, data[i] as u32);
+                i += 1;
+            }
+        }
+    }
+
+    // end of block
+    write_literal(&mut w, 256);
+
+    w.finish()
+}
+
+fn match_length(data: &[u8], i: usize, distance: usize) -> usize {
+    let max = MAX_MATCH.min(data.len() - i);
+
+    (0..max)
+        .take_while(|n| data[i + n] == data[i + n - distance])
+        .count()
+}
+
+fn write_literal(w: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => w.write_code(0x30 + symbol, 8),
+        144..=255 => w.write_code(0x190 + symbol - 144, 9),
+        256..=279 => w.write_code(symbol - 256, 7),
+        _ => w.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+/// base length and extra bits of length codes 257 to 285
+const LENGTHS: [(usize, u32); 29] = [
+    (3, 0),
+    (4, 0),
+    (5, 0),
+    (6, 0),
+    (7, 0),
+    (8, 0),
+    (9, 0),
+    (10, 0),
+    (11, 1),
+    (13, 1),
+    (15, 1),
+    (17, 1),
+    (19, 2),
+    (23, 2),
+    (27, 2),
+    (31, 2),
+    (35, 3),
+    (43, 3),
+    (51, 3),
+    (59, 3),
+    (67, 4),
+    (83, 4),
+    (99, 4),
+    (115, 4),
+    (131, 5),
+    (163, 5),
+    (195, 5),
+    (227, 5),
+    (258, 0),
+];
+
+fn write_length(w: &mut BitWriter, length: usize) {
+    let code = LENGTHS
+        .iter()
+        .rposition(|(base, _)| *base <= length)
+        .unwrap();
+    let (base, extra) = LENGTHS[code];
+
+    write_literal(w, 257 + code as u32);
+    w.write((length - base) as u32, extra);
+}
+
+fn write_distance(w: &mut BitWriter, distance: usize) {
+    // only the two distances the matcher uses, neither needs extra bits
+    let code = match distance {
+        1 => 0,
+        4 => 3,
+        _ => unreachable!("no distance code for {distance}"),
+    };
+
+    w.write_code(code, 5);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::export::encode_png;
+    use winit::dpi::PhysicalSize;
+
+    /// Reads bits least significant first, the other side of `BitWriter`
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+            self.pos += 1;
+            bit as u32
+        }
+
+        fn bits(&mut self, count: u32) -> u32 {
+            (0..count).map(|i| self.bit() << i).sum()
+        }
+
+        /// Huffman codes come most significant bit first
+        fn code(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        fn literal(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return code + 256;
+            }
+
+            let code = code << 1 | self.bit();
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => code - 0xc0 + 280,
+                _ => (code << 1 | self.bit()) - 0x190 + 144,
+            }
+        }
+    }
+
+    /// Just enough inflate for what `deflate` writes, fixed huffman blocks
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut r = BitReader { data, pos: 0 };
+        let mut out: Vec<u8> = Vec::new();
+
+        loop {
+            let last = r.bit() == 1;
+            assert_eq!(r.bits(2), 1, "only fixed huffman blocks get written");
+
+            loop {
+                let symbol = r.literal();
+                match symbol {
+                    0..=255 => out.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let (base, extra) = LENGTHS[symbol as usize - 257];
+                        let length = base + r.bits(extra) as usize;
+
+                        let distance = match r.code(5) {
+                            code @ 0..=3 => code as usize + 1,
+                            code => {
+                                let extra = code / 2 - 1;
+                                let base = (2 << extra) + 1 + ((code as usize % 2) << extra);
+                                base + r.bits(extra) as usize
+                            }
+                        };
+
+                        for _ in 0..length {
+                            out.push(out[out.len() - distance]);
+                        }
+                    }
+                }
+            }
+
+            if last {
+                return out;
+            }
+        }
+    }
+
+    /// Splits a png into its chunks, checking every crc on the way
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut chunks = Vec::new();
+        let mut rest = png;
+
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = &rest[8..8 + len];
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+
+            assert_eq!(crc, crc32(&rest[4..8 + len]), "bad crc on {kind:?}");
+            chunks.push((kind, data));
+            rest = &rest[12 + len..];
+        }
+
+        chunks
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn round_trips_a_small_canvas() {
+        // runs of the same pixel so the repeats get used, and translucent premultiplied ones
+        #[rustfmt::skip]
+        let pixels = [
+            0x00000000, 0x00000000, 0x80402010, 0x80402010, 0x80402010,
+            0xffff5555, 0xffff5555, 0xffff5555, 0xffff5555, 0x01010101,
+        ];
+        #[rustfmt::skip]
+        let rgba = [
+            [0, 0, 0, 0], [0, 0, 0, 0], [0x80, 0x40, 0x20, 0x80], [0x80, 0x40, 0x20, 0x80], [0x80, 0x40, 0x20, 0x80],
+            [0xff, 0x55, 0x55, 0xff], [0xff, 0x55, 0x55, 0xff], [0xff, 0x55, 0x55, 0xff], [0xff, 0x55, 0x55, 0xff], [0xff, 0xff, 0xff, 0x01],
+        ];
+
+        let png = encode_png(&pixels, PhysicalSize::new(5, 2));
+        assert_eq!(png[..8], SIGNATURE);
+
+        let chunks = chunks(&png[8..]);
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+
+        let header = chunks[0].1;
+        assert_eq!(header[..8], [0, 0, 0, 5, 0, 0, 0, 2]);
+        assert_eq!(header[8..], [8, 6, 0, 0, 0]);
+
+        let zlib = chunks[1].1;
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+
+        let raw = inflate(&zlib[2..zlib.len() - 4]);
+        let adler = u32::from_be_bytes(zlib[zlib.len() - 4..].try_into().unwrap());
+        assert_eq!(adler, adler32(&raw));
+
+        // each row is its filter type, 0, then the pixels as they are
+        for (row, expected) in raw.chunks(1 + 5 * 4).zip(rgba.chunks(5)) {
+            assert_eq!(row[0], 0);
+            assert_eq!(row[1..], expected.concat());
+        }
+        assert_eq!(raw.len(), 2 * (1 + 5 * 4));
+    }
+}
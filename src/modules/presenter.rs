@@ -0,0 +1,53 @@
+use std::{error::Error, num::NonZeroU32, sync::Arc};
+
+use softbuffer::Surface;
+use wgpu::rwh::DisplayHandle;
+use winit::{dpi::PhysicalSize, window::Window};
+
+use crate::app::program::Application;
+
+/// Something that can put the canvas pixels on screen
+pub trait Presenter {
+    fn resize(&mut self, size: PhysicalSize<u32>) -> Result<(), Box<dyn Error>>;
+
+    fn present(&mut self, pixels: &[u32]) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct SurfacePresenter {
+    surface: Surface<DisplayHandle<'static>, Arc<Window>>,
+}
+
+impl SurfacePresenter {
+    pub fn new(app: &Application, window: Arc<Window>) -> Result<Self, Box<dyn Error>> {
+        let surface = Surface::new(app.context.as_ref().unwrap(), window)?;
+
+        Ok(Self { surface })
+    }
+}
+
+impl Presenter for SurfacePresenter {
+    fn resize(&mut self, size: PhysicalSize<u32>) -> Result<(), Box<dyn Error>> {
+        let (width, height) = match (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Ok(()),
+        };
+
+        self.surface.resize(width, height)?;
+
+        Ok(())
+    }
+
+    fn present(&mut self, pixels: &[u32]) -> Result<(), Box<dyn Error>> {
+        let mut buf = self.surface.buffer_mut()?;
+
+        // the surface can lag a frame behind a resize, just skip presenting until it catches up
+        if buf.len() != pixels.len() {
+            return Ok(());
+        }
+
+        buf.copy_from_slice(pixels);
+        buf.present()?;
+
+        Ok(())
+    }
+}
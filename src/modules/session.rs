@@ -0,0 +1,497 @@
+// session files keep everything needed to pick a drawing back up later: the strokes, the undo history
+// and the brush. theyre toml like the rest of the files we write, the pixels are never saved since
+// they can always be rendered again from the strokes
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalSize;
+
+use super::brush::BrushEdge;
+use super::canvas::{EraseMode, StrokeChange, BRUSH_SIZE, DEFAULT_BRUSH_COLOR};
+use super::layer::LayerProps;
+use super::stroke::{Stroke, Tool};
+use crate::platform;
+
+/// Format this version writes, bumped whenever something gets added
+pub const VERSION: u32 = 4;
+
+/// Oldest format that can read what this version writes. Only bumped when older versions
+/// would get a file wrong instead of just skipping the parts they dont know about
+const MIN_VERSION: u32 = 1;
+
+/// Fills and whole canvas changes came in with format 2, version 1 cant make sense of them
+const CANVAS_CHANGES_VERSION: u32 = 2;
+
+/// Layers came in with format 4, older versions would only see an empty canvas
+const LAYERS_VERSION: u32 = 4;
+
+/// ```toml
+/// version = 4
+/// min_version = 1
+/// width = 1920
+/// height = 1080
+///
+/// [settings]
+/// brush_color = "#ffccaaff"
+/// brush_size = 2
+///
+/// [[strokes]]
+/// color = "#ffccaaff"
+/// width = 2
+/// tool = "Pen"
+/// points = [[10.0, 10.0, 0.0, 1.0], [12.0, 11.0, 0.016, 1.0]]
+///
+/// [[undo]]
+/// kind = "Added"
+/// index = 0
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Format the file was written in
+    pub version: u32,
+    /// Oldest format that can read the file, newer versions only ever add things older ones can skip
+    pub min_version: u32,
+
+    /// Size of the canvas it was drawn on
+    pub width: u32,
+    pub height: u32,
+
+    #[serde(default)]
+    pub settings: SessionSettings,
+
+    /// Every stroke in draw order, when the drawing is all on one layer
+    #[serde(default)]
+    pub strokes: Vec<Stroke>,
+    /// Bottom to top, empty when everything is in `strokes`
+    #[serde(default)]
+    pub layers: Vec<SessionLayer>,
+    /// Layer that was being drawn on
+    #[serde(default, skip_serializing_if = "is_bottom")]
+    pub active_layer: usize,
+
+    /// Oldest first, the last one is what gets undone next
+    #[serde(default)]
+    pub undo: Vec<Step>,
+    /// The last one is what gets redone next
+    #[serde(default)]
+    pub redo: Vec<Step>,
+    /// Steps that were undone and then drawn over, each one listed after the step it was done after
+    #[serde(default)]
+    pub branches: Vec<Branch>,
+}
+
+/// The brush the drawing was left with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionSettings {
+    #[serde(with = "crate::app::settings::color")]
+    pub brush_color: u32,
+    pub brush_size: u32,
+    pub brush_edge: BrushEdge,
+    pub tool: Tool,
+    pub erase_mode: EraseMode,
+    #[serde(with = "crate::app::settings::optional_color")]
+    pub background: Option<u32>,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            brush_color: DEFAULT_BRUSH_COLOR,
+            brush_size: BRUSH_SIZE,
+            brush_edge: BrushEdge::default(),
+            tool: Tool::Pen,
+            erase_mode: EraseMode::default(),
+            background: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionLayer {
+    #[serde(flatten)]
+    pub props: LayerProps,
+    #[serde(default)]
+    pub strokes: Vec<Stroke>,
+}
+
+/// A single step of the history and the layer it was made on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    #[serde(default, skip_serializing_if = "is_bottom")]
+    pub layer: usize,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+/// The bottom layer isnt written out, which keeps drawings without layers readable by versions that dont have them
+fn is_bottom(layer: &usize) -> bool {
+    *layer == 0
+}
+
+/// A `StrokeChange` as its saved, the pixel side of the history gets rebuilt when loading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Change {
+    Added {
+        index: usize,
+        stroke: Stroke,
+    },
+    Edited {
+        index: usize,
+        before: Stroke,
+        after: Stroke,
+    },
+    /// `strokes[n]` was at `indices[n]` before it got erased
+    Removed {
+        indices: Vec<usize>,
+        strokes: Vec<Stroke>,
+    },
+    /// `strokes` went on top of everything else
+    Pasted {
+        strokes: Vec<Stroke>,
+    },
+    /// Every stroke at once, like clearing the canvas
+    Replaced {
+        before: Vec<Stroke>,
+        after: Vec<Stroke>,
+    },
+    /// A new empty layer at the steps layer
+    AddedLayer {
+        props: LayerProps,
+    },
+    /// The steps layer was put on top of the one under it
+    MergedLayer {
+        props: LayerProps,
+        strokes: Vec<Stroke>,
+    },
+}
+
+impl From<&StrokeChange> for Change {
+    fn from(change: &StrokeChange) -> Self {
+        match change.clone() {
+            StrokeChange::Added(index, stroke) => Change::Added { index, stroke },
+            StrokeChange::Edited {
+                index,
+                before,
+                after,
+            } => Change::Edited {
+                index,
+                before,
+                after,
+            },
+            StrokeChange::Removed(removed) => {
+                let (indices, strokes) = removed.into_iter().unzip();
+                Change::Removed { indices, strokes }
+            }
+            StrokeChange::Pasted(strokes) => Change::Pasted { strokes },
+            StrokeChange::Replaced { before, after } => Change::Replaced { before, after },
+            StrokeChange::AddedLayer(props) => Change::AddedLayer { props },
+            StrokeChange::MergedLayer { props, strokes } => Change::MergedLayer { props, strokes },
+        }
+    }
+}
+
+impl TryFrom<Change> for StrokeChange {
+    type Error = String;
+
+    fn try_from(change: Change) -> Result<Self, Self::Error> {
+        Ok(match change {
+            Change::Added { index, stroke } => StrokeChange::Added(index, stroke),
+            Change::Edited {
+                index,
+                before,
+                after,
+            } => StrokeChange::Edited {
+                index,
+                before,
+                after,
+            },
+            Change::Removed { indices, strokes } => {
+                if indices.len() != strokes.len() {
+                    return Err(format!(
+                        "{} erased strokes but {} places they were erased from",
+                        strokes.len(),
+                        indices.len()
+                    ));
+                }
+
+                StrokeChange::Removed(indices.into_iter().zip(strokes).collect())
+            }
+            Change::Pasted { strokes } => StrokeChange::Pasted(strokes),
+            Change::Replaced { before, after } => StrokeChange::Replaced { before, after },
+            Change::AddedLayer { props } => StrokeChange::AddedLayer(props),
+            Change::MergedLayer { props, strokes } => StrokeChange::MergedLayer { props, strokes },
+        })
+    }
+}
+
+/// A step off to the side of the undo and redo history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Branch {
+    /// Step this was done after, None for the very start. Steps are counted from 0 going through the
+    /// undo history oldest first, then the redo history in the order itd be redone, then the branches
+    pub parent: Option<usize>,
+    #[serde(flatten)]
+    pub step: Step,
+}
+
+/// Just enough of the file to tell whether the rest can be read
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+    min_version: Option<u32>,
+}
+
+impl Session {
+    pub fn new(size: PhysicalSize<u32>, settings: SessionSettings, strokes: Vec<Stroke>) -> Self {
+        Self {
+            version: VERSION,
+            min_version: MIN_VERSION,
+            width: size.width,
+            height: size.height,
+            settings,
+            strokes,
+            layers: Vec::new(),
+            active_layer: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+
+    /// Oldest format that can read this session, so files without anything new stay readable by older versions
+    pub fn required_version(&self) -> u32 {
+        let steps = || {
+            (self.undo.iter())
+                .chain(&self.redo)
+                .chain(self.branches.iter().map(|b| &b.step))
+        };
+
+        let layered = !self.layers.is_empty()
+            || steps().any(|step| {
+                step.layer != 0
+                    || matches!(
+                        step.change,
+                        Change::AddedLayer { .. } | Change::MergedLayer { .. }
+                    )
+            });
+        if layered {
+            return LAYERS_VERSION;
+        }
+
+        let fills = |strokes: &[Stroke]| strokes.iter().any(|s| s.tool == Tool::Fill);
+
+        let canvas_changes = fills(&self.strokes)
+            || steps().map(|step| &step.change).any(|change| match change {
+                Change::Added { stroke, .. } => stroke.tool == Tool::Fill,
+                Change::Edited { before, after, .. } => {
+                    before.tool == Tool::Fill || after.tool == Tool::Fill
+                }
+                Change::Removed { strokes, .. } => fills(strokes),
+                Change::Pasted { .. } | Change::Replaced { .. } => true,
+                Change::AddedLayer { .. } | Change::MergedLayer { .. } => true,
+            });
+
+        match canvas_changes {
+            true => CANVAS_CHANGES_VERSION,
+            false => MIN_VERSION,
+        }
+    }
+
+    /// Where the drawing gets saved when draw mode is left or the app closes
+    pub fn autosave_path() -> Option<PathBuf> {
+        Some(platform::data_dir()?.join("draw").join("autosave.draw"))
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.width, self.height)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(src: &str) -> Result<Self, Box<dyn Error>> {
+        // check the version before anything else, a newer format might not parse at all
+        let header: Header = toml::from_str(src)?;
+        let min_version = header.min_version.unwrap_or(header.version);
+
+        if min_version > VERSION {
+            return Err(format!(
+                "this session needs a newer version of draw (format {}, this one reads up to {VERSION})",
+                header.version
+            )
+            .into());
+        }
+
+        Ok(toml::from_str(src)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // same as the settings, a crash halfway through shouldnt take the last good session with it
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, toml::to_string(self)?)?;
+        std::fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+    use super::*;
+    use crate::modules::canvas::Canvas;
+
+    /// Drags the brush through `points` like the mouse would
+    fn stroke(canvas: &mut Canvas, points: &[(f64, f64)]) {
+        canvas.invert_drawing();
+        for &(x, y) in points {
+            let p = PhysicalPosition::new(x, y);
+            canvas.draw(p, p).unwrap();
+        }
+        canvas.invert_drawing();
+    }
+
+    /// Saves the canvas the way it gets written to disk and opens it on a fresh one
+    fn round_trip(canvas: &Canvas) -> Canvas {
+        let src = toml::to_string(&canvas.session()).unwrap();
+
+        let mut restored = Canvas::headless(canvas.canvas_size);
+        restored.restore(Session::parse(&src).unwrap()).unwrap();
+        restored
+    }
+
+    #[test]
+    fn round_trips_layers_and_branches() {
+        let mut canvas = Canvas::headless(PhysicalSize::new(64, 48));
+
+        stroke(&mut canvas, &[(4.0, 4.0), (30.0, 10.0), (50.0, 30.0)]);
+        stroke(&mut canvas, &[(10.0, 40.0), (40.0, 5.0)]);
+        canvas.undo();
+        stroke(&mut canvas, &[(5.0, 20.0), (60.0, 20.0)]);
+        canvas.fill(0x40ffffff);
+
+        canvas.add_layer();
+        canvas.brush_color = 0xffff5555;
+        stroke(&mut canvas, &[(20.0, 2.0), (20.0, 45.0)]);
+        canvas.paste(vec![canvas.strokes[0].clone()]);
+
+        canvas.add_layer();
+        stroke(&mut canvas, &[(2.0, 30.0), (62.0, 32.0)]);
+        canvas.merge_layer_down().unwrap();
+        canvas.change_layer_opacity(-0.5);
+
+        canvas.clear();
+        canvas.undo();
+
+        let session = canvas.session();
+        assert_eq!(session.layers.len(), 2);
+        assert!(!session.branches.is_empty());
+        assert!(!session.redo.is_empty());
+
+        let mut restored = round_trip(&canvas);
+        assert_eq!(restored.session(), session);
+        assert_eq!(restored.flattened(), canvas.flattened());
+
+        // the history has to keep working the same way, not just look the same
+        while canvas.undo() {
+            assert!(restored.undo());
+            assert_eq!(restored.flattened(), canvas.flattened());
+        }
+        assert!(!restored.undo());
+
+        assert_eq!(restored.switch_branch(1), canvas.switch_branch(1));
+        while canvas.redo() {
+            assert!(restored.redo());
+            assert_eq!(restored.flattened(), canvas.flattened());
+        }
+        assert!(!restored.redo());
+    }
+
+    #[test]
+    fn drawing_without_layers_saves_for_version_1() {
+        let mut canvas = Canvas::headless(PhysicalSize::new(32, 32));
+        stroke(&mut canvas, &[(4.0, 4.0), (20.0, 12.0)]);
+
+        let session = canvas.session();
+        assert!(session.layers.is_empty());
+        assert_eq!(session.min_version, MIN_VERSION);
+
+        assert_eq!(round_trip(&canvas).session(), session);
+    }
+
+    #[test]
+    fn rejects_newer_formats() {
+        let newer = VERSION + 1;
+
+        let src = format!("version = {newer}\nmin_version = {newer}\nwidth = 8\nheight = 8\n");
+        assert!(Session::parse(&src).is_err());
+
+        // without a min_version the version itself is what it needs
+        let src = format!("version = {newer}\nwidth = 8\nheight = 8\n");
+        assert!(Session::parse(&src).is_err());
+
+        // newer files that only added things this version skips still open
+        let src = format!(
+            "version = {newer}\nmin_version = 1\nwidth = 8\nheight = 8\nsomething_new = 1\n"
+        );
+        assert!(Session::parse(&src).is_ok());
+    }
+
+    /// A session the way the first format wrote them, before layers and branches
+    const VERSION_1: &str = r##"
+version = 1
+min_version = 1
+width = 32
+height = 32
+
+[settings]
+brush_color = "#ff5555ff"
+brush_size = 3
+
+[[strokes]]
+color = "#ffccaaff"
+width = 2
+tool = "Pen"
+points = [[4.0, 4.0, 0.0, 1.0], [20.0, 12.0, 0.016, 1.0]]
+
+[[undo]]
+kind = "Added"
+index = 0
+
+[undo.stroke]
+color = "#ffccaaff"
+width = 2
+tool = "Pen"
+points = [[4.0, 4.0, 0.0, 1.0], [20.0, 12.0, 0.016, 1.0]]
+"##;
+
+    #[test]
+    fn loads_version_1() {
+        let session = Session::parse(VERSION_1).unwrap();
+        assert!(session.layers.is_empty() && session.branches.is_empty());
+        assert_eq!(session.undo[0].layer, 0);
+
+        let mut canvas = Canvas::headless(session.size());
+        canvas.restore(session).unwrap();
+
+        assert_eq!(canvas.strokes.len(), 1);
+        assert_eq!((canvas.brush_color, canvas.brush_size), (0xffff5555, 3));
+        assert!(canvas.flattened().iter().any(|p| *p != 0));
+
+        assert!(canvas.undo());
+        assert!(canvas.strokes.is_empty());
+        assert!(canvas.flattened().iter().all(|p| *p == 0));
+    }
+}
@@ -0,0 +1,87 @@
+use std::f64::consts::{FRAC_PI_4, TAU};
+
+use winit::dpi::PhysicalPosition;
+
+use super::brush::distance;
+use super::stroke::Tool;
+
+type Pos = PhysicalPosition<f64>;
+
+/// Start and end of a shape dragged from `anchor` to `cursor`.
+/// `constrain` snaps lines to 45° and makes rectangles/ellipses square,
+/// `from_center` grows the shape out of the anchor instead of from its corner
+pub fn constrain(
+    tool: Tool,
+    anchor: Pos,
+    cursor: Pos,
+    constrain: bool,
+    from_center: bool,
+) -> (Pos, Pos) {
+    let (mut dx, mut dy) = (cursor.x - anchor.x, cursor.y - anchor.y);
+
+    if constrain {
+        match tool {
+            Tool::Line | Tool::Arrow => {
+                let length = (dx * dx + dy * dy).sqrt();
+                let angle = (dy.atan2(dx) / FRAC_PI_4).round() * FRAC_PI_4;
+
+                (dx, dy) = (length * angle.cos(), length * angle.sin());
+            }
+            _ => {
+                let side = dx.abs().max(dy.abs());
+
+                (dx, dy) = (side.copysign(dx), side.copysign(dy));
+            }
+        }
+    }
+
+    let end = PhysicalPosition::new(anchor.x + dx, anchor.y + dy);
+
+    match from_center {
+        true => (PhysicalPosition::new(anchor.x - dx, anchor.y - dy), end),
+        false => (anchor, end),
+    }
+}
+
+/// Polyline that traces the shape, None for tools that arent shapes
+pub fn outline(tool: Tool, start: Pos, end: Pos, width: u32) -> Option<Vec<Pos>> {
+    let points = match tool {
+        Tool::Line => vec![start, end],
+        Tool::Rectangle => vec![
+            start,
+            PhysicalPosition::new(end.x, start.y),
+            end,
+            PhysicalPosition::new(start.x, end.y),
+            start,
+        ],
+        Tool::Ellipse => {
+            let center = PhysicalPosition::new((start.x + end.x) / 2.0, (start.y + end.y) / 2.0);
+            let (rx, ry) = ((end.x - start.x).abs() / 2.0, (end.y - start.y).abs() / 2.0);
+
+            // about one point every 4 pixels around the edge
+            let steps = ((TAU * rx.max(ry) / 4.0).ceil() as usize).clamp(16, 720);
+
+            (0..=steps)
+                .map(|i| {
+                    let a = TAU * i as f64 / steps as f64;
+                    PhysicalPosition::new(center.x + rx * a.cos(), center.y + ry * a.sin())
+                })
+                .collect()
+        }
+        Tool::Arrow => {
+            let length = distance(start, end);
+            let head = (width as f64 * 4.0).max(10.0).min(length / 3.0);
+            let angle = (end.y - start.y).atan2(end.x - start.x);
+
+            let barb = |side: f64| {
+                let a = angle + std::f64::consts::PI + side * FRAC_PI_4 * 0.75;
+                PhysicalPosition::new(end.x + head * a.cos(), end.y + head * a.sin())
+            };
+
+            vec![start, end, barb(1.0), end, barb(-1.0)]
+        }
+        Tool::Pen | Tool::Eraser | Tool::Highlighter | Tool::Fill => return None,
+    };
+
+    Some(points)
+}
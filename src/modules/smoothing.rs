@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalPosition;
+
+use super::brush::distance;
+
+type Pos = PhysicalPosition<f64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Smoothing {
+    /// Draw curves through the cursor samples instead of straight lines,
+    /// costs one sample of latency since the curve needs to know where the cursor goes next
+    pub spline: bool,
+
+    /// Lazy brush radius, the brush only follows the cursor once its further away than this.
+    /// 0 turns the stabilizer off
+    pub stabilizer: f64,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self {
+            spline: true,
+            stabilizer: 0.0,
+        }
+    }
+}
+
+/// Turns raw cursor samples into the points that actually end up in a stroke
+#[derive(Debug, Clone, Default)]
+pub struct Smoother {
+    pub settings: Smoothing,
+
+    brush: Option<Pos>,
+    samples: Vec<Pos>,
+}
+
+impl Smoother {
+    pub fn new(settings: Smoothing) -> Self {
+        Self {
+            settings,
+            brush: None,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn begin(&mut self) {
+        self.brush = None;
+        self.samples.clear();
+    }
+
+    /// Feeds a cursor sample, returns the new points to append to the stroke
+    pub fn feed(&mut self, location: Pos) -> Vec<Pos> {
+        let Some(p) = self.stabilize(location) else {
+            return Vec::new();
+        };
+
+        if !self.settings.spline {
+            return vec![p];
+        }
+
+        self.samples.push(p);
+        if self.samples.len() > 4 {
+            self.samples.remove(0);
+        }
+
+        match self.samples.as_slice() {
+            [p0] => vec![*p0],
+            [_, _] => Vec::new(),
+            [p0, p1, p2] => catmull_rom(*p0, *p0, *p1, *p2),
+            [p0, p1, p2, p3] => catmull_rom(*p0, *p1, *p2, *p3),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Everything that is still held back once the stroke ends
+    pub fn finish(&mut self) -> Vec<Pos> {
+        let tail = match self.samples.as_slice() {
+            [] | [_] => Vec::new(),
+            [p0, p1] => catmull_rom(*p0, *p0, *p1, *p1),
+            [.., p0, p1, p2] => catmull_rom(*p0, *p1, *p2, *p2),
+        };
+
+        self.begin();
+        tail
+    }
+
+    fn stabilize(&mut self, location: Pos) -> Option<Pos> {
+        let radius = self.settings.stabilizer;
+
+        let brush = match self.brush {
+            None => location,
+            Some(brush) => {
+                let d = distance(brush, location);
+
+                // duplicate samples dont add anything, and inside the radius the brush stays put
+                if d <= radius.max(0.0) || d == 0.0 {
+                    return None;
+                }
+
+                let t = (d - radius.max(0.0)) / d;
+                PhysicalPosition::new(
+                    brush.x + (location.x - brush.x) * t,
+                    brush.y + (location.y - brush.y) * t,
+                )
+            }
+        };
+
+        self.brush = Some(brush);
+        Some(brush)
+    }
+}
+
+/// Points on the curve from `p1` to `p2`, not including `p1` since thats already part of the stroke
+fn catmull_rom(p0: Pos, p1: Pos, p2: Pos, p3: Pos) -> Vec<Pos> {
+    // roughly one point every 2 pixels, more than that doesnt make the curve any smoother
+    let steps = ((distance(p1, p2) / 2.0).ceil() as usize).clamp(1, 32);
+
+    (1..=steps)
+        .map(|i| {
+            let t = i as f64 / steps as f64;
+            let (t2, t3) = (t * t, t * t * t);
+
+            let axis = |a: f64, b: f64, c: f64, d: f64| {
+                0.5 * ((2.0 * b)
+                    + (-a + c) * t
+                    + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+                    + (-a + 3.0 * b - 3.0 * c + d) * t3)
+            };
+
+            PhysicalPosition::new(axis(p0.x, p1.x, p2.x, p3.x), axis(p0.y, p1.y, p2.y, p3.y))
+        })
+        .collect()
+}
@@ -0,0 +1,298 @@
+use std::{borrow::Cow, collections::HashMap, str::FromStr, time::Duration};
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use winit::dpi::PhysicalPosition;
+
+use super::brush::{
+    distance, distance_to_segment, get_segment_coverage, get_segment_points, BrushEdge,
+};
+use super::pen::{PenState, PressureCurve};
+use super::pixels::{blend_over, erase};
+use super::shapes::outline;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tool {
+    Pen,
+    /// Removes ink back to transparency
+    Eraser,
+    /// Wide translucent ink, going over the same spot twice in one stroke doesnt make it darker
+    Highlighter,
+
+    // shapes only keep their start and end point, the outline is generated when drawing
+    Line,
+    Rectangle,
+    Ellipse,
+    Arrow,
+
+    /// Covers the whole rectangle between its two points, what filling the canvas puts down.
+    /// Not something to draw with, so it cant be picked as a tool
+    Fill,
+}
+
+impl FromStr for Tool {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "Pen" => Ok(Tool::Pen),
+            "Eraser" => Ok(Tool::Eraser),
+            "Highlighter" => Ok(Tool::Highlighter),
+            "Line" => Ok(Tool::Line),
+            "Rectangle" => Ok(Tool::Rectangle),
+            "Ellipse" => Ok(Tool::Ellipse),
+            "Arrow" => Ok(Tool::Arrow),
+            other => Err(format!("unknown tool \"{other}\"")),
+        }
+    }
+}
+
+impl Tool {
+    pub fn is_shape(&self) -> bool {
+        matches!(
+            self,
+            Tool::Line | Tool::Rectangle | Tool::Ellipse | Tool::Arrow
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrokePoint {
+    pub x: f64,
+    pub y: f64,
+
+    /// Time since the stroke was started
+    pub time: Duration,
+
+    pub pressure: f32,
+    pub tilt: Option<(f32, f32)>,
+}
+
+impl StrokePoint {
+    pub fn position(&self) -> PhysicalPosition<f64> {
+        PhysicalPosition::new(self.x, self.y)
+    }
+}
+
+/// Saved as `[x, y, seconds, pressure]`, with the tilt on the end when the pen reported one.
+/// Strokes have a lot of points so they cant each be a table
+impl Serialize for StrokePoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut values = vec![
+            self.x,
+            self.y,
+            self.time.as_secs_f64(),
+            self.pressure as f64,
+        ];
+        if let Some((x, y)) = self.tilt {
+            values.extend([x as f64, y as f64]);
+        }
+
+        values.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StrokePoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<f64>::deserialize(deserializer)?;
+
+        let tilt = match values.len() {
+            4 => None,
+            6 => Some((values[4] as f32, values[5] as f32)),
+            n => return Err(D::Error::invalid_length(n, &"4 or 6 numbers")),
+        };
+
+        Ok(Self {
+            x: values[0],
+            y: values[1],
+            time: Duration::try_from_secs_f64(values[2]).map_err(D::Error::custom)?,
+            pressure: values[3] as f32,
+            tilt,
+        })
+    }
+}
+
+/// A single stroke kept as vector data, the canvas pixels are just a rendering of these
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stroke {
+    pub points: Vec<StrokePoint>,
+
+    #[serde(with = "crate::app::settings::color")]
+    pub color: u32,
+    pub width: u32,
+    pub tool: Tool,
+    #[serde(default)]
+    pub edge: BrushEdge,
+    #[serde(default)]
+    pub pressure: PressureCurve,
+}
+
+impl Stroke {
+    pub fn new(color: u32, width: u32, tool: Tool, edge: BrushEdge) -> Self {
+        Self {
+            points: Vec::new(),
+            color,
+            width,
+            tool,
+            edge,
+            pressure: PressureCurve::default(),
+        }
+    }
+
+    pub fn push(&mut self, location: PhysicalPosition<f64>, time: Duration, pen: &PenState) {
+        self.points.push(StrokePoint {
+            x: location.x,
+            y: location.y,
+            time,
+            pressure: pen.pressure,
+            tilt: pen.tilt,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// (min, max) corners of the points, not including the brush width
+    pub fn bounds(&self) -> Option<(PhysicalPosition<f64>, PhysicalPosition<f64>)> {
+        let first = self.points.first()?.position();
+
+        Some(self.points.iter().fold((first, first), |(min, max), p| {
+            (
+                PhysicalPosition::new(min.x.min(p.x), min.y.min(p.y)),
+                PhysicalPosition::new(max.x.max(p.x), max.y.max(p.y)),
+            )
+        }))
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        self.points.iter_mut().for_each(|p| {
+            p.x += dx;
+            p.y += dy;
+        });
+    }
+
+    /// The points ink actually goes along, for shapes thats their outline
+    pub fn path(&self) -> Cow<'_, [StrokePoint]> {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return Cow::Borrowed(&self.points);
+        };
+
+        match outline(self.tool, first.position(), last.position(), self.width) {
+            None => Cow::Borrowed(&self.points),
+            Some(outline) => Cow::Owned(
+                outline
+                    .into_iter()
+                    .map(|p| StrokePoint {
+                        x: p.x,
+                        y: p.y,
+                        ..*last
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Whether `location` is within `tolerance` pixels of the inked part of the stroke
+    pub fn hit_test(&self, location: PhysicalPosition<f64>, tolerance: f64) -> bool {
+        // fills are under everything, picking them up wherever you click would get in the way
+        if self.tool == Tool::Fill {
+            return false;
+        }
+
+        let reach = self.width as f64 + tolerance;
+
+        match self.path().as_ref() {
+            [] => false,
+            [p] => distance(p.position(), location) <= reach,
+            points => points
+                .windows(2)
+                .any(|w| distance_to_segment(location, w[0].position(), w[1].position()) <= reach),
+        }
+    }
+
+    /// How much of every pixel the segment from `a` to `b` covers when drawn at `scale`
+    pub fn segment_coverage(
+        &self,
+        a: &StrokePoint,
+        b: &StrokePoint,
+        scale: f64,
+    ) -> Vec<((i32, i32), f32)> {
+        let size = |p: &StrokePoint| {
+            self.width as f64 * self.pressure.width_factor(p.pressure) as f64 * scale
+        };
+        let scaled = |p: &StrokePoint| PhysicalPosition::new(p.x * scale, p.y * scale);
+
+        let (opacity_a, opacity_b) = (
+            self.pressure.opacity(a.pressure),
+            self.pressure.opacity(b.pressure),
+        );
+
+        match self.edge {
+            BrushEdge::Hard => get_segment_points(scaled(a), scaled(b), size(a), size(b))
+                .into_iter()
+                .map(|(p, t)| (p, opacity_a + (opacity_b - opacity_a) * t as f32))
+                .collect(),
+            BrushEdge::Smooth => get_segment_coverage(
+                scaled(a),
+                scaled(b),
+                BrushEdge::smooth_radius(size(a)),
+                BrushEdge::smooth_radius(size(b)),
+            )
+            .into_iter()
+            .map(|(p, c)| (p, c * opacity_a.max(opacity_b)))
+            .collect(),
+        }
+    }
+
+    /// Top left and bottom right pixel a fill covers at `scale`, None for everything thats not a fill
+    pub fn fill_area(&self, scale: f64) -> Option<((i32, i32), (i32, i32))> {
+        let (Tool::Fill, Some((min, max))) = (self.tool, self.bounds()) else {
+            return None;
+        };
+
+        Some((
+            ((min.x * scale) as i32, (min.y * scale) as i32),
+            ((max.x * scale) as i32, (max.y * scale) as i32),
+        ))
+    }
+
+    /// How much of every pixel the whole stroke covers when drawn at `scale`.
+    /// Overlapping segments take the highest coverage instead of adding up.
+    /// Fills are better drawn straight from `fill_area`, this is a map entry for every pixel
+    pub fn coverage(&self, scale: f64) -> HashMap<(i32, i32), f32> {
+        let mut coverage = HashMap::new();
+
+        if let Some(((x_min, y_min), (x_max, y_max))) = self.fill_area(scale) {
+            for y in y_min..=y_max {
+                coverage.extend((x_min..=x_max).map(|x| ((x, y), 1.0)));
+            }
+
+            return coverage;
+        }
+
+        let mut add = |segment: Vec<((i32, i32), f32)>| {
+            for (p, c) in segment {
+                let entry = coverage.entry(p).or_insert(0.0f32);
+                *entry = entry.max(c);
+            }
+        };
+
+        match self.path().as_ref() {
+            [] => {}
+            [p] => add(self.segment_coverage(p, p, scale)),
+            all => all.windows(2).for_each(|w| {
+                add(self.segment_coverage(&w[0], &w[1], scale));
+            }),
+        }
+
+        coverage
+    }
+
+    /// What a pixel that was `dst` before the stroke becomes with `coverage` of this stroke on it
+    pub fn composite(&self, dst: u32, coverage: f32) -> u32 {
+        match self.tool {
+            Tool::Eraser => erase(dst, coverage),
+            _ => blend_over(dst, self.color, coverage),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+// svg export, straight from the stroke list so it stays sharp at any size.
+// svg cant vary the width along a path, so pressure is averaged over the whole stroke.
+// erasers cant just be painted on top either, everything drawn before one gets masked by it instead.
+// every layer gets a group of its own so erasers only mask the layer theyre on
+
+use std::fmt::Write;
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use super::shapes::outline;
+use super::stroke::{Stroke, Tool};
+
+/// The whole drawing as an svg document. `layers` are the strokes of every layer bottom to top
+/// with their opacity, `background` goes under everything
+pub fn encode(
+    layers: &[(&[Stroke], f32)],
+    size: PhysicalSize<u32>,
+    background: Option<u32>,
+) -> String {
+    let (width, height) = (size.width, size.height);
+
+    let mut defs = String::new();
+    let mut body = String::new();
+    let mut masks = 0;
+
+    for (strokes, layer_opacity) in layers {
+        let mut layer = String::new();
+
+        for stroke in strokes.iter().filter(|s| !s.is_empty()) {
+            match stroke.tool {
+                Tool::Eraser => {
+                    let id = format!("erase-{masks}");
+                    masks += 1;
+
+                    // white keeps whatever was drawn, the eraser is painted over it in black
+                    let _ = writeln!(
+                        defs,
+                        r#"<mask id="{id}" maskUnits="userSpaceOnUse" x="0" y="0" width="{width}" height="{height}"><rect width="100%" height="100%" fill="white"/>{}</mask>"#,
+                        element(stroke, "#000000", 1.0),
+                    );
+
+                    layer = format!("<g mask=\"url(#{id})\">\n{layer}</g>\n");
+                }
+                _ => {
+                    let (color, opacity) = (hex(stroke.color), alpha(stroke.color));
+                    let _ = writeln!(layer, "{}", element(stroke, &color, opacity));
+                }
+            }
+        }
+
+        let _ = write!(
+            body,
+            "<g opacity=\"{}\">\n{layer}</g>\n",
+            num(*layer_opacity as f64)
+        );
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    svg.push('\n');
+
+    if !defs.is_empty() {
+        let _ = write!(svg, "<defs>\n{defs}</defs>\n");
+    }
+
+    if let Some(bg) = background {
+        let _ = writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}" fill-opacity="{}"/>"#,
+            hex(bg),
+            num(alpha(bg))
+        );
+    }
+
+    svg.push_str(&body);
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// A single stroke in `color`, shapes become the matching svg element and everything else a path
+fn element(stroke: &Stroke, color: &str, opacity: f64) -> String {
+    let points: Vec<PhysicalPosition<f64>> = stroke.points.iter().map(|p| p.position()).collect();
+
+    let (first, last) = (points[0], points[points.len() - 1]);
+
+    // fills cover whole pixels instead of running a brush through their middle
+    if stroke.tool == Tool::Fill {
+        return format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{color}" fill-opacity="{}"/>"#,
+            num(first.x.min(last.x).floor()),
+            num(first.y.min(last.y).floor()),
+            num((last.x - first.x).abs().floor() + 1.0),
+            num((last.y - first.y).abs().floor() + 1.0),
+            num(opacity),
+        );
+    }
+
+    let (x1, y1, x2, y2) = (
+        num(first.x + 0.5),
+        num(first.y + 0.5),
+        num(last.x + 0.5),
+        num(last.y + 0.5),
+    );
+
+    let shape = match stroke.tool {
+        Tool::Line => format!(r#"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}"/>"#),
+        Tool::Rectangle => format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+            num(first.x.min(last.x) + 0.5),
+            num(first.y.min(last.y) + 0.5),
+            num((last.x - first.x).abs()),
+            num((last.y - first.y).abs()),
+        ),
+        Tool::Ellipse => format!(
+            r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}"/>"#,
+            num((first.x + last.x) / 2.0 + 0.5),
+            num((first.y + last.y) / 2.0 + 0.5),
+            num((last.x - first.x).abs() / 2.0),
+            num((last.y - first.y).abs() / 2.0),
+        ),
+        Tool::Arrow => {
+            // the outline goes start, tip, one barb, tip, the other barb
+            let head = outline(Tool::Arrow, first, last, stroke.width).unwrap();
+
+            format!(
+                r#"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}"/><polyline points="{} {} {}"/>"#,
+                point(&head[2]),
+                point(&head[1]),
+                point(&head[4]),
+            )
+        }
+        Tool::Fill => unreachable!("fills are handled above"),
+        Tool::Pen | Tool::Eraser | Tool::Highlighter => {
+            let mut d = format!("M{}", point(&first));
+
+            // a single point still needs a segment for the round cap to show up as a dot
+            let rest = match points.len() {
+                1 => &points[..],
+                _ => &points[1..],
+            };
+            for p in rest {
+                let _ = write!(d, " L{}", point(p));
+            }
+
+            format!(r#"<path d="{d}"/>"#)
+        }
+    };
+
+    let count = stroke.points.len() as f64;
+    let pressure = |f: &dyn Fn(f32) -> f32| {
+        stroke
+            .points
+            .iter()
+            .map(|p| f(p.pressure) as f64)
+            .sum::<f64>()
+            / count
+    };
+
+    // the rasterized brush ends up `width * 2 - 1` pixels across
+    let width =
+        (stroke.width as f64 * 2.0 - 1.0).max(1.0) * pressure(&|p| stroke.pressure.width_factor(p));
+    let opacity = opacity * pressure(&|p| stroke.pressure.opacity(p));
+
+    format!(
+        r#"<g fill="none" stroke="{color}" stroke-opacity="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round">{shape}</g>"#,
+        num(opacity),
+        num(width),
+    )
+}
+
+/// Moved onto the middle of the pixel, which is where the rasterizer puts the brush
+fn point(p: &PhysicalPosition<f64>) -> String {
+    format!("{},{}", num(p.x + 0.5), num(p.y + 0.5))
+}
+
+/// At most 2 decimals and no trailing zeros, strokes have a lot of points
+fn num(n: f64) -> String {
+    let s = format!("{n:.2}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+fn hex(color: u32) -> String {
+    format!("#{:06x}", color & 0x00ffffff)
+}
+
+fn alpha(color: u32) -> f64 {
+    ((color >> 24) & 0xff) as f64 / 255.0
+}
@@ -0,0 +1,734 @@
+// just enough of the d-bus wire protocol to talk to the session bus, theres no d-bus library
+// in the dependencies and the tray only needs a handful of calls.
+// messages are always written little endian, but both byte orders get read
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{Read, Write},
+    os::unix::{fs::MetadataExt, net::UnixStream},
+    path::Path,
+};
+
+pub const METHOD_CALL: u8 = 1;
+pub const METHOD_RETURN: u8 = 2;
+pub const ERROR: u8 = 3;
+pub const SIGNAL: u8 = 4;
+
+/// Set on calls that dont want anything back
+pub const NO_REPLY_EXPECTED: u8 = 1;
+
+/// The bus refuses anything bigger than this anyway
+const MAX_MESSAGE: usize = 128 * 1024 * 1024;
+
+/// A single d-bus value, arrays keep their element signature so empty ones can still be written
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    Uint16(u16),
+    Int32(i32),
+    Uint32(u32),
+    Int64(i64),
+    Uint64(u64),
+    Double(f64),
+    Str(String),
+    Path(String),
+    Signature(String),
+    UnixFd(u32),
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    pub fn str(s: impl Into<String>) -> Self {
+        Self::Str(s.into())
+    }
+
+    pub fn variant(v: Value) -> Self {
+        Self::Variant(Box::new(v))
+    }
+
+    /// An `a{sv}`, the usual way of passing properties around
+    pub fn dict(entries: Vec<(&str, Value)>) -> Self {
+        let entries = (entries.into_iter())
+            .map(|(k, v)| Self::DictEntry(Box::new(Self::str(k)), Box::new(Self::variant(v))))
+            .collect();
+
+        Self::Array("{sv}".into(), entries)
+    }
+
+    pub fn signature(&self) -> String {
+        match self {
+            Self::Byte(_) => "y".into(),
+            Self::Bool(_) => "b".into(),
+            Self::Int16(_) => "n".into(),
+            Self::Uint16(_) => "q".into(),
+            Self::Int32(_) => "i".into(),
+            Self::Uint32(_) => "u".into(),
+            Self::Int64(_) => "x".into(),
+            Self::Uint64(_) => "t".into(),
+            Self::Double(_) => "d".into(),
+            Self::Str(_) => "s".into(),
+            Self::Path(_) => "o".into(),
+            Self::Signature(_) => "g".into(),
+            Self::UnixFd(_) => "h".into(),
+            Self::Array(element, _) => format!("a{element}"),
+            Self::Struct(fields) => {
+                let fields: String = fields.iter().map(Value::signature).collect();
+                format!("({fields})")
+            }
+            Self::DictEntry(k, v) => format!("{{{}{}}}", k.signature(), v.signature()),
+            Self::Variant(_) => "v".into(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) | Self::Path(s) | Self::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Self::Int32(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(_, items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_struct(&self) -> Option<&[Value]> {
+        match self {
+            Self::Struct(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+/// How far a value of the type starting with `code` has to be aligned
+fn alignment(code: u8) -> usize {
+    match code {
+        b'n' | b'q' => 2,
+        b'b' | b'i' | b'u' | b'h' | b's' | b'o' | b'a' => 4,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 1,
+    }
+}
+
+/// Splits the first complete type off of a signature
+fn split_type(sig: &str) -> Result<(&str, &str), String> {
+    let bytes = sig.as_bytes();
+
+    let end = match bytes.first() {
+        None => return Err("ran out of signature".into()),
+        Some(b'a') => 1 + split_type(&sig[1..])?.0.len(),
+        Some(open @ (b'(' | b'{')) => {
+            let close = if *open == b'(' { b')' } else { b'}' };
+            let mut depth = 0;
+
+            let end = bytes.iter().position(|b| {
+                match *b {
+                    b if b == *open => depth += 1,
+                    b if b == close => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            });
+
+            end.ok_or_else(|| format!("unclosed {} in \"{sig}\"", *open as char))? + 1
+        }
+        Some(_) => 1,
+    };
+
+    Ok(sig.split_at(end))
+}
+
+fn types(mut sig: &str) -> Result<Vec<&str>, String> {
+    let mut types = Vec::new();
+
+    while !sig.is_empty() {
+        let (ty, rest) = split_type(sig)?;
+        types.push(ty);
+        sig = rest;
+    }
+
+    Ok(types)
+}
+
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn pad(&mut self, n: usize) {
+        while !self.buf.len().is_multiple_of(n) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.pad(4);
+        self.buf.extend(n.to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Byte(b) => self.buf.push(*b),
+            Value::Bool(b) => self.u32(*b as u32),
+            Value::Int16(n) => {
+                self.pad(2);
+                self.buf.extend(n.to_le_bytes());
+            }
+            Value::Uint16(n) => {
+                self.pad(2);
+                self.buf.extend(n.to_le_bytes());
+            }
+            Value::Int32(n) => self.u32(*n as u32),
+            Value::Uint32(n) | Value::UnixFd(n) => self.u32(*n),
+            Value::Int64(n) => {
+                self.pad(8);
+                self.buf.extend(n.to_le_bytes());
+            }
+            Value::Uint64(n) => {
+                self.pad(8);
+                self.buf.extend(n.to_le_bytes());
+            }
+            Value::Double(n) => {
+                self.pad(8);
+                self.buf.extend(n.to_le_bytes());
+            }
+            Value::Str(s) | Value::Path(s) => self.string(s),
+            Value::Signature(s) => {
+                self.buf.push(s.len() as u8);
+                self.buf.extend(s.as_bytes());
+                self.buf.push(0);
+            }
+            Value::Array(element, items) => {
+                self.u32(0);
+                let len_at = self.buf.len() - 4;
+
+                // the padding before the first element doesnt count towards the length
+                self.pad(alignment(element.as_bytes()[0]));
+                let start = self.buf.len();
+                items.iter().for_each(|item| self.value(item));
+
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.pad(8);
+                fields.iter().for_each(|field| self.value(field));
+            }
+            Value::DictEntry(k, v) => {
+                self.pad(8);
+                self.value(k);
+                self.value(v);
+            }
+            Value::Variant(v) => {
+                self.value(&Value::Signature(v.signature()));
+                self.value(v);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Decoder<'a> {
+    fn align(&mut self, n: usize) -> Result<(), String> {
+        self.pos = self.pos.div_ceil(n) * n;
+
+        match self.pos <= self.buf.len() {
+            true => Ok(()),
+            false => Err("message ends early".into()),
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = (self.buf.get(self.pos..self.pos + n)).ok_or("message ends early")?;
+        self.pos += n;
+
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        self.align(N)?;
+
+        let mut bytes: [u8; N] = self.take(N)?.try_into().unwrap();
+        if self.big_endian {
+            bytes.reverse();
+        }
+
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.fixed()?))
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, String> {
+        let bytes = self.take(len + 1)?;
+
+        String::from_utf8(bytes[..len].to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn value(&mut self, ty: &str) -> Result<Value, String> {
+        let value = match ty.as_bytes()[0] {
+            b'y' => Value::Byte(self.take(1)?[0]),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::Int16(i16::from_le_bytes(self.fixed()?)),
+            b'q' => Value::Uint16(u16::from_le_bytes(self.fixed()?)),
+            b'i' => Value::Int32(i32::from_le_bytes(self.fixed()?)),
+            b'u' => Value::Uint32(self.u32()?),
+            b'h' => Value::UnixFd(self.u32()?),
+            b'x' => Value::Int64(i64::from_le_bytes(self.fixed()?)),
+            b't' => Value::Uint64(u64::from_le_bytes(self.fixed()?)),
+            b'd' => Value::Double(f64::from_le_bytes(self.fixed()?)),
+            b's' | b'o' => {
+                let len = self.u32()? as usize;
+                let s = self.string(len)?;
+
+                match ty.as_bytes()[0] {
+                    b's' => Value::Str(s),
+                    _ => Value::Path(s),
+                }
+            }
+            b'g' => {
+                let len = self.take(1)?[0] as usize;
+                Value::Signature(self.string(len)?)
+            }
+            b'a' => {
+                let len = self.u32()? as usize;
+                let element = &ty[1..];
+
+                self.align(alignment(element.as_bytes()[0]))?;
+                let end = self.pos + len;
+                if end > self.buf.len() {
+                    return Err("array runs past the end of the message".into());
+                }
+
+                let mut items = Vec::new();
+                while self.pos < end {
+                    items.push(self.value(element)?);
+                }
+
+                Value::Array(element.to_string(), items)
+            }
+            b'(' => {
+                self.align(8)?;
+
+                let fields = types(&ty[1..ty.len() - 1])?;
+                Value::Struct(
+                    fields
+                        .into_iter()
+                        .map(|f| self.value(f))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            b'{' => {
+                self.align(8)?;
+
+                let [k, v] = types(&ty[1..ty.len() - 1])?[..] else {
+                    return Err(format!("\"{ty}\" isnt a dict entry"));
+                };
+                Value::DictEntry(Box::new(self.value(k)?), Box::new(self.value(v)?))
+            }
+            b'v' => {
+                let Value::Signature(sig) = self.value("g")? else {
+                    unreachable!()
+                };
+
+                let (inner, "") = split_type(&sig)? else {
+                    return Err(format!("variant with more than one type \"{sig}\""));
+                };
+                Value::Variant(Box::new(self.value(inner)?))
+            }
+            other => return Err(format!("unknown type \"{}\"", other as char)),
+        };
+
+        Ok(value)
+    }
+}
+
+/// A message in either direction. `body` is its arguments, the signature follows from them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    pub kind: u8,
+    pub flags: u8,
+    pub serial: u32,
+
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    pub fn call(destination: &str, path: &str, interface: &str, member: &str) -> Self {
+        Self {
+            kind: METHOD_CALL,
+            destination: Some(destination.into()),
+            path: Some(path.into()),
+            interface: Some(interface.into()),
+            member: Some(member.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn reply(to: &Message, body: Vec<Value>) -> Self {
+        Self {
+            kind: METHOD_RETURN,
+            reply_serial: Some(to.serial),
+            destination: to.sender.clone(),
+            body,
+            ..Default::default()
+        }
+    }
+
+    pub fn error(to: &Message, name: &str, text: &str) -> Self {
+        Self {
+            kind: ERROR,
+            error_name: Some(name.into()),
+            reply_serial: Some(to.serial),
+            destination: to.sender.clone(),
+            body: vec![Value::str(text)],
+            ..Default::default()
+        }
+    }
+
+    pub fn with_body(mut self, body: Vec<Value>) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub fn is_call(&self, interface: &str, member: &str) -> bool {
+        self.kind == METHOD_CALL
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        let mut field = |code: u8, value: Value| {
+            fields.push(Value::Struct(vec![
+                Value::Byte(code),
+                Value::variant(value),
+            ]));
+        };
+
+        let strings = [
+            (1, &self.path),
+            (2, &self.interface),
+            (3, &self.member),
+            (4, &self.error_name),
+            (6, &self.destination),
+            (7, &self.sender),
+        ];
+        for (code, s) in strings {
+            if let Some(s) = s {
+                match code {
+                    1 => field(code, Value::Path(s.clone())),
+                    _ => field(code, Value::Str(s.clone())),
+                }
+            }
+        }
+        if let Some(serial) = self.reply_serial {
+            field(5, Value::Uint32(serial));
+        }
+
+        let signature: String = self.body.iter().map(Value::signature).collect();
+        if !signature.is_empty() {
+            field(8, Value::Signature(signature));
+        }
+
+        let mut e = Encoder::default();
+        e.buf.extend([b'l', self.kind, self.flags, 1]);
+        e.u32(0);
+        e.u32(self.serial);
+        e.value(&Value::Array("(yv)".into(), fields));
+        e.pad(8);
+
+        let start = e.buf.len();
+        self.body.iter().for_each(|v| e.value(v));
+
+        let len = (e.buf.len() - start) as u32;
+        e.buf[4..8].copy_from_slice(&len.to_le_bytes());
+
+        e.buf
+    }
+
+    /// Parses a whole message, `buf` has to start at the first byte of it
+    pub fn decode(buf: &[u8]) -> Result<Self, String> {
+        let big_endian = match buf.first() {
+            Some(b'l') => false,
+            Some(b'B') => true,
+            _ => return Err("not a d-bus message".into()),
+        };
+
+        let mut d = Decoder {
+            buf,
+            pos: 4,
+            big_endian,
+        };
+        let body_len = d.u32()? as usize;
+
+        let mut message = Message {
+            kind: buf[1],
+            flags: buf[2],
+            serial: d.u32()?,
+            ..Default::default()
+        };
+
+        let mut signature = String::new();
+        for field in d.value("a(yv)")?.as_array().unwrap() {
+            let [Value::Byte(code), Value::Variant(value)] = field.as_struct().unwrap() else {
+                unreachable!()
+            };
+
+            let s = value.as_str().map(str::to_string);
+            match (code, value.as_ref()) {
+                (1, _) => message.path = s,
+                (2, _) => message.interface = s,
+                (3, _) => message.member = s,
+                (4, _) => message.error_name = s,
+                (5, Value::Uint32(serial)) => message.reply_serial = Some(*serial),
+                (6, _) => message.destination = s,
+                (7, _) => message.sender = s,
+                (8, _) => signature = s.unwrap_or_default(),
+                _ => {}
+            }
+        }
+
+        d.align(8)?;
+        let body_start = d.pos;
+        if body_start + body_len > buf.len() {
+            return Err("message ends early".into());
+        }
+
+        // the body is aligned from its own start, which is always a multiple of 8 in
+        let mut body = Decoder {
+            buf: &buf[body_start..body_start + body_len],
+            pos: 0,
+            big_endian,
+        };
+        for ty in types(&signature)? {
+            message.body.push(body.value(ty)?);
+        }
+
+        Ok(message)
+    }
+}
+
+/// A connection to the session bus
+pub struct Connection {
+    stream: UnixStream,
+    serial: u32,
+    /// Unique name the bus gave us, like `:1.42`
+    pub name: String,
+    /// Messages that came in while waiting on a reply
+    queue: VecDeque<Message>,
+}
+
+impl Connection {
+    pub fn session() -> Result<Self, Box<dyn Error>> {
+        let address = match std::env::var("DBUS_SESSION_BUS_ADDRESS") {
+            Ok(address) => address,
+            Err(_) => {
+                let runtime = std::env::var("XDG_RUNTIME_DIR")
+                    .map_err(|_| "theres no session bus to connect to")?;
+                format!("unix:path={runtime}/bus")
+            }
+        };
+
+        let mut last_error: Box<dyn Error> = "the session bus address is empty".into();
+        for address in address.split(';').filter(|a| !a.is_empty()) {
+            match Self::connect(address) {
+                Ok(connection) => return Ok(connection),
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn connect(address: &str) -> Result<Self, Box<dyn Error>> {
+        let options = (address.strip_prefix("unix:"))
+            .ok_or_else(|| format!("cant connect to \"{address}\", only unix sockets work"))?;
+        let option = |key: &str| {
+            (options.split(','))
+                .find_map(|kv| kv.strip_prefix(key)?.strip_prefix('='))
+                .map(unescape)
+        };
+
+        let stream = match (option("path"), option("abstract")) {
+            (Some(path), _) => UnixStream::connect(path)?,
+            (None, Some(name)) => {
+                use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+                UnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?)?
+            }
+            _ => return Err(format!("cant connect to \"{address}\"").into()),
+        };
+
+        let mut connection = Self {
+            stream,
+            serial: 0,
+            name: String::new(),
+            queue: VecDeque::new(),
+        };
+        connection.authenticate()?;
+
+        let reply = connection.call(Message::call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+        ))?;
+        connection.name = (reply.first().and_then(Value::as_str))
+            .ok_or("the bus didnt say who we are")?
+            .to_string();
+
+        Ok(connection)
+    }
+
+    /// The bus knows who we are from the socket, it just wants the uid to match
+    fn authenticate(&mut self) -> Result<(), Box<dyn Error>> {
+        let uid = Path::new("/proc/self").metadata()?.uid();
+        let hex: String = (uid.to_string().bytes())
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        self.stream.write_all(b"\0")?;
+        self.stream
+            .write_all(format!("AUTH EXTERNAL {hex}\r\n").as_bytes())?;
+
+        // read a byte at a time, anything after the line belongs to the binary protocol
+        let mut line = Vec::new();
+        while !line.ends_with(b"\r\n") {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+
+        if !line.starts_with(b"OK ") {
+            let line = String::from_utf8_lossy(&line);
+            return Err(format!("the bus wouldnt let us in: {}", line.trim()).into());
+        }
+
+        self.stream.write_all(b"BEGIN\r\n")?;
+
+        Ok(())
+    }
+
+    /// Sends a message and returns the serial it went out with
+    pub fn send(&mut self, mut message: Message) -> Result<u32, Box<dyn Error>> {
+        self.serial += 1;
+        message.serial = self.serial;
+
+        self.stream.write_all(&message.encode())?;
+
+        Ok(message.serial)
+    }
+
+    /// Calls a method and waits for what it returns
+    pub fn call(&mut self, message: Message) -> Result<Vec<Value>, Box<dyn Error>> {
+        let serial = self.send(message)?;
+
+        loop {
+            let message = self.read()?;
+
+            if message.reply_serial != Some(serial) {
+                self.queue.push_back(message);
+                continue;
+            }
+
+            return match message.kind {
+                ERROR => {
+                    let name = message.error_name.unwrap_or_default();
+                    match message.body.first().and_then(Value::as_str) {
+                        Some(text) => Err(format!("{name}: {text}").into()),
+                        None => Err(name.into()),
+                    }
+                }
+                _ => Ok(message.body),
+            };
+        }
+    }
+
+    /// Next message that isnt a reply to one of our calls
+    pub fn next(&mut self) -> Result<Message, Box<dyn Error>> {
+        match self.queue.pop_front() {
+            Some(message) => Ok(message),
+            None => self.read(),
+        }
+    }
+
+    fn read(&mut self) -> Result<Message, Box<dyn Error>> {
+        let mut fixed = [0u8; 16];
+        self.stream.read_exact(&mut fixed)?;
+
+        let u32_at = |i: usize| {
+            let bytes: [u8; 4] = fixed[i..i + 4].try_into().unwrap();
+            match fixed[0] {
+                b'B' => u32::from_be_bytes(bytes),
+                _ => u32::from_le_bytes(bytes),
+            }
+        };
+
+        let fields = (u32_at(12) as usize).div_ceil(8) * 8;
+        let len = 16 + fields + u32_at(4) as usize;
+        if len > MAX_MESSAGE {
+            return Err("the bus sent a message thats too big".into());
+        }
+
+        let mut buf = fixed.to_vec();
+        buf.resize(len, 0);
+        self.stream.read_exact(&mut buf[16..])?;
+
+        Ok(Message::decode(&buf)?)
+    }
+}
+
+/// Addresses escape anything unusual as `%xx`
+fn unescape(s: &str) -> String {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+
+    while let Some(b) = bytes.next() {
+        let escaped = (b == b'%')
+            .then(|| {
+                let hex = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()
+            })
+            .flatten();
+
+        out.push(escaped.unwrap_or(b));
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,221 @@
+use std::{
+    error::Error,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::EventLoop,
+    platform::x11::{WindowAttributesExtX11, WindowType},
+    raw_window_handle::{HasDisplayHandle, RawDisplayHandle},
+    window::{Window, WindowAttributes, WindowLevel},
+};
+
+use super::sni::{Item, Pixmap, StatusNotifier};
+use super::PenInfo;
+use crate::app::apphandler::{TrayEvent, UserEvent};
+use crate::modules::export;
+
+/// The tray icon lives on the session bus, without a bus or a panel to show it theres just no icon
+pub struct Tray {
+    item: Option<StatusNotifier>,
+}
+
+impl Tray {
+    pub fn new(event_loop: &EventLoop<UserEvent>) -> Result<Self, Box<dyn Error>> {
+        let proxy = event_loop.create_proxy();
+
+        let item = Item {
+            title: "draw".into(),
+            icon: tray_icon(include_bytes!("../../assets/pencil.ico")),
+            activate: TrayEvent::Show,
+            menu: vec![
+                ("Show".into(), TrayEvent::Show),
+                ("Quit".into(), TrayEvent::Exit),
+            ],
+        };
+
+        let item = StatusNotifier::new(item, move |e: TrayEvent| {
+            let _ = proxy.send_event(e.into());
+        });
+
+        let item = item
+            .map_err(|e| eprintln!("couldnt add a tray icon: {e}"))
+            .ok();
+
+        Ok(Self { item })
+    }
+
+    /// The panel draws the menu itself
+    pub fn show_menu(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.item
+            .as_ref()
+            .is_some_and(StatusNotifier::is_registered)
+    }
+}
+
+/// The icon is a single 32 bit bmp entry, panels want its pixels as big endian ARGB rows from the top
+fn tray_icon(ico: &[u8]) -> Option<Pixmap> {
+    let u32_at = |i: usize| Some(u32::from_le_bytes(ico.get(i..i + 4)?.try_into().ok()?));
+
+    let offset = u32_at(18)? as usize;
+    let bmp = ico.get(offset..)?;
+    let header = u32::from_le_bytes(bmp.get(0..4)?.try_into().ok()?) as usize;
+    let width = i32::from_le_bytes(bmp.get(4..8)?.try_into().ok()?);
+    // the height counts the transparency mask under the image too
+    let height = i32::from_le_bytes(bmp.get(8..12)?.try_into().ok()?) / 2;
+    let bits = u16::from_le_bytes(bmp.get(14..16)?.try_into().ok()?);
+
+    if bits != 32 || width <= 0 || height <= 0 {
+        return None;
+    }
+
+    let row = width as usize * 4;
+    let pixels = bmp.get(header..header + row * height as usize)?;
+
+    let argb = (pixels.chunks_exact(row).rev())
+        .flat_map(|row| row.chunks_exact(4))
+        .flat_map(|bgra| [bgra[3], bgra[2], bgra[1], bgra[0]])
+        .collect();
+
+    Some((width, height, argb))
+}
+
+/// Global hotkeys come from raw device events, which wayland doesnt hand out
+pub fn global_keys(display: &impl HasDisplayHandle) -> bool {
+    !matches!(
+        display.display_handle().map(|h| h.as_raw()),
+        Ok(RawDisplayHandle::Wayland(_))
+    )
+}
+
+pub fn window_attributes(attributes: WindowAttributes) -> WindowAttributes {
+    // the x11 bits are just ignored when running under wayland
+    // utility windows dont show up in the taskbar on most window managers
+    attributes
+        .with_window_level(WindowLevel::AlwaysOnTop)
+        .with_name("draw", "draw")
+        .with_x11_window_type(vec![WindowType::Utility])
+}
+
+// wayland doesnt let us unminimize a window, so instead of minimizing we hide it
+// and let clicks pass through it in case the compositor ignores set_visible
+pub fn show_window(window: &Window) {
+    window.set_minimized(false);
+    window.set_visible(true);
+    let _ = window.set_cursor_hittest(true);
+    window.focus_window();
+}
+
+pub fn hide_window(window: &Window) {
+    let _ = window.set_cursor_hittest(false);
+    window.set_visible(false);
+}
+
+/// Tablets only get pressure, tilt and the eraser end on windows.
+/// winit turns an x11 pen into plain mouse input and doesnt speak wayland's tablet protocol,
+/// so on linux a pen draws like a mouse, only touchscreens that report force get pressure
+pub fn pen_info(_touch_id: u64) -> Option<PenInfo> {
+    None
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+}
+
+/// Desktops keep the pictures folder in `user-dirs.dirs`, its only called Pictures in english
+pub fn pictures_dir() -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+
+    let user_dirs = config_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join("user-dirs.dirs")).ok())
+        .and_then(|src| user_dir(&src, "XDG_PICTURES_DIR", &home));
+
+    user_dirs.or_else(|| Some(home.join("Pictures")))
+}
+
+/// Finds `name` in a `user-dirs.dirs` file, which has lines like `XDG_PICTURES_DIR="$HOME/Bilder"`.
+/// Paths in there are either absolute or relative to the home dir
+fn user_dir(src: &str, name: &str, home: &Path) -> Option<PathBuf> {
+    let value = src
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| line.strip_prefix(name)?.trim_start().strip_prefix('='))?;
+
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    match value.strip_prefix("$HOME") {
+        // just the home dir means the folder was turned off
+        Some(rest) => Some(home.join(rest.strip_prefix('/')?)).filter(|p| p != home),
+        None => Some(PathBuf::from(value)).filter(|p| p.is_absolute()),
+    }
+}
+
+/// Theres no clipboard api to call on linux, so the png gets handed to wl-copy or xclip,
+/// which keep serving it after they return
+pub fn copy_image(pixels: &[u32], size: PhysicalSize<u32>) -> Result<(), Box<dyn Error>> {
+    let mut tools: Vec<(&str, &[&str])> = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(("wl-copy", &["--type", "image/png"]));
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        tools.push((
+            "xclip",
+            &["-selection", "clipboard", "-t", "image/png", "-i"],
+        ));
+    }
+
+    if tools.is_empty() {
+        return Err("no clipboard available without a display".into());
+    }
+
+    let png = export::encode_png(pixels, size);
+
+    for (tool, args) in tools {
+        let mut child = match Command::new(tool)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        // dropping stdin closes it, which is how they know the image is done
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&png)?;
+        }
+
+        let status = child.wait()?;
+        return match status.success() {
+            true => Ok(()),
+            false => Err(format!("{tool} failed ({status})").into()),
+        };
+    }
+
+    Err("no clipboard available, install wl-clipboard or xclip".into())
+}
@@ -0,0 +1,22 @@
+// Everything that only makes sense on one OS lives behind these modules,
+// the rest of the app should only ever talk to `platform::*`
+#[cfg(target_os = "windows")]
+mod windows;
+#[cfg(target_os = "windows")]
+pub use self::windows::*;
+
+#[cfg(not(target_os = "windows"))]
+mod dbus;
+#[cfg(not(target_os = "windows"))]
+mod linux;
+#[cfg(not(target_os = "windows"))]
+mod sni;
+#[cfg(not(target_os = "windows"))]
+pub use self::linux::*;
+
+/// Extra pen details that winit doesnt report, only windows has a way to ask for them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PenInfo {
+    pub eraser: bool,
+    pub tilt: Option<(f32, f32)>,
+}
@@ -0,0 +1,408 @@
+// the linux tray icon is a StatusNotifierItem, kde, gnome (with the appindicator extension),
+// waybar and most other panels show those. the panel draws the menu itself from what we
+// describe over com.canonical.dbusmenu, so it looks like the rest of the desktop
+
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use super::dbus::{Connection, Message, Value, NO_REPLY_EXPECTED, SIGNAL};
+
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER: &str = "org.freedesktop.DBus.Peer";
+
+/// Width, height and ARGB32 pixels in network byte order, the way the panel wants them
+pub type Pixmap = (i32, i32, Vec<u8>);
+
+/// What the icon shows and what clicking it sends
+pub struct Item<T> {
+    pub title: String,
+    pub icon: Option<Pixmap>,
+    /// Sent on a left click
+    pub activate: T,
+    /// Menu entries, in order
+    pub menu: Vec<(String, T)>,
+}
+
+/// A tray icon thats served from its own thread for as long as the app runs
+pub struct StatusNotifier {
+    registered: Arc<AtomicBool>,
+}
+
+impl StatusNotifier {
+    pub fn new<T: Copy + Send + 'static>(
+        item: Item<T>,
+        send: impl Fn(T) + Send + 'static,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut bus = Connection::session()?;
+
+        let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+        bus.call(bus_call("RequestName").with_body(vec![Value::str(&name), Value::Uint32(4)]))?;
+
+        // the watcher goes away when the panel restarts, it forgets every item when it does
+        bus.call(bus_call("AddMatch").with_body(vec![Value::str(format!(
+            "type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',\
+             member='NameOwnerChanged',arg0='{WATCHER}'"
+        ))]))?;
+
+        let registered = Arc::new(AtomicBool::new(false));
+        match register(&mut bus, &name) {
+            Ok(()) => registered.store(true, Ordering::Relaxed),
+            Err(e) => eprintln!("no tray to put the icon in yet: {e}"),
+        }
+
+        let server = Server {
+            bus,
+            name,
+            item,
+            send,
+            registered: registered.clone(),
+        };
+        std::thread::spawn(move || server.run());
+
+        Ok(Self { registered })
+    }
+
+    /// Whether a panel is showing the icon right now
+    pub fn is_registered(&self) -> bool {
+        self.registered.load(Ordering::Relaxed)
+    }
+}
+
+fn bus_call(member: &str) -> Message {
+    Message::call(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        member,
+    )
+}
+
+fn register(bus: &mut Connection, name: &str) -> Result<(), Box<dyn Error>> {
+    let call = Message::call(
+        WATCHER,
+        "/StatusNotifierWatcher",
+        WATCHER,
+        "RegisterStatusNotifierItem",
+    );
+    bus.call(call.with_body(vec![Value::str(name)]))?;
+
+    Ok(())
+}
+
+struct Server<T, F> {
+    bus: Connection,
+    name: String,
+    item: Item<T>,
+    send: F,
+    registered: Arc<AtomicBool>,
+}
+
+impl<T: Copy, F: Fn(T)> Server<T, F> {
+    fn run(mut self) {
+        loop {
+            let message = match self.bus.next() {
+                Ok(message) => message,
+                Err(e) => {
+                    eprintln!("lost the session bus, the tray icon is gone: {e}");
+                    self.registered.store(false, Ordering::Relaxed);
+                    return;
+                }
+            };
+
+            if message.kind == SIGNAL {
+                self.watcher_changed(&message);
+                continue;
+            }
+
+            let reply = match self.handle(&message) {
+                Ok(body) => Message::reply(&message, body),
+                Err((name, text)) => Message::error(&message, name, &text),
+            };
+
+            if message.flags & NO_REPLY_EXPECTED == 0 {
+                let _ = self.bus.send(reply);
+            }
+        }
+    }
+
+    fn watcher_changed(&mut self, message: &Message) {
+        if message.member.as_deref() != Some("NameOwnerChanged") {
+            return;
+        }
+
+        let [name, _, owner] = &message.body[..] else {
+            return;
+        };
+        if name.as_str() != Some(WATCHER) {
+            return;
+        }
+
+        let registered = match owner.as_str() {
+            Some("") | None => false,
+            Some(_) => register(&mut self.bus, &self.name).is_ok(),
+        };
+        self.registered.store(registered, Ordering::Relaxed);
+    }
+
+    fn handle(&mut self, m: &Message) -> Result<Vec<Value>, (&'static str, String)> {
+        let path = m.path.as_deref().unwrap_or_default();
+        let args = &m.body;
+        let arg_str = |i: usize| args.get(i).and_then(Value::as_str).unwrap_or_default();
+        let arg_i32 = |i: usize| args.get(i).and_then(Value::as_i32).unwrap_or_default();
+
+        if m.is_call(PEER, "Ping") {
+            return Ok(vec![]);
+        }
+
+        if m.is_call(INTROSPECTABLE, "Introspect") {
+            return Ok(vec![Value::str(introspect(path))]);
+        }
+
+        if m.is_call(PROPERTIES, "Get") {
+            let (interface, name) = (arg_str(0), arg_str(1));
+
+            return match self
+                .properties(path, interface)
+                .into_iter()
+                .find(|(n, _)| *n == name)
+            {
+                Some((_, value)) => Ok(vec![Value::variant(value)]),
+                None => Err((
+                    "org.freedesktop.DBus.Error.UnknownProperty",
+                    format!("theres no {interface}.{name} on {path}"),
+                )),
+            };
+        }
+
+        if m.is_call(PROPERTIES, "GetAll") {
+            return Ok(vec![Value::dict(self.properties(path, arg_str(0)))]);
+        }
+
+        match (path, m.interface.as_deref(), m.member.as_deref()) {
+            (ITEM_PATH, Some(ITEM_INTERFACE), Some("Activate" | "SecondaryActivate")) => {
+                (self.send)(self.item.activate);
+                Ok(vec![])
+            }
+
+            // the menu is described over dbusmenu, theres nothing to pop up ourselves
+            (ITEM_PATH, Some(ITEM_INTERFACE), Some("ContextMenu" | "Scroll")) => Ok(vec![]),
+
+            (MENU_PATH, Some(MENU_INTERFACE), Some("GetLayout")) => {
+                let layout = match arg_i32(0) {
+                    0 => self.root(arg_i32(1) != 0),
+                    id => self.entry(id).ok_or_else(|| unknown_entry(id))?,
+                };
+
+                Ok(vec![Value::Uint32(1), layout])
+            }
+
+            (MENU_PATH, Some(MENU_INTERFACE), Some("GetGroupProperties")) => {
+                let ids: Vec<i32> = match args.first().and_then(Value::as_array) {
+                    Some(ids) if !ids.is_empty() => ids.iter().filter_map(Value::as_i32).collect(),
+                    _ => (1..=self.item.menu.len() as i32).collect(),
+                };
+
+                let groups = (ids.into_iter())
+                    .filter_map(|id| self.entry(id))
+                    .map(|entry| match entry {
+                        Value::Struct(mut fields) => {
+                            fields.pop();
+                            Value::Struct(fields)
+                        }
+                        other => other,
+                    })
+                    .collect();
+
+                Ok(vec![Value::Array("(ia{sv})".into(), groups)])
+            }
+
+            (MENU_PATH, Some(MENU_INTERFACE), Some("GetProperty")) => {
+                let id = arg_i32(0);
+                let label = self.label(id).ok_or_else(|| unknown_entry(id))?;
+
+                match arg_str(1) {
+                    "label" => Ok(vec![Value::variant(Value::str(label))]),
+                    "enabled" | "visible" => Ok(vec![Value::variant(Value::Bool(true))]),
+                    name => Err((
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        format!("menu entries dont have a {name}"),
+                    )),
+                }
+            }
+
+            (MENU_PATH, Some(MENU_INTERFACE), Some("Event")) => {
+                self.clicked(arg_i32(0), arg_str(1));
+                Ok(vec![])
+            }
+
+            (MENU_PATH, Some(MENU_INTERFACE), Some("EventGroup")) => {
+                for event in args.first().and_then(Value::as_array).unwrap_or_default() {
+                    if let Some([id, name, ..]) = event.as_struct() {
+                        self.clicked(
+                            id.as_i32().unwrap_or_default(),
+                            name.as_str().unwrap_or_default(),
+                        );
+                    }
+                }
+
+                Ok(vec![Value::Array("i".into(), vec![])])
+            }
+
+            // the menu never changes, so it never needs updating before its shown
+            (MENU_PATH, Some(MENU_INTERFACE), Some("AboutToShow")) => Ok(vec![Value::Bool(false)]),
+            (MENU_PATH, Some(MENU_INTERFACE), Some("AboutToShowGroup")) => Ok(vec![
+                Value::Array("i".into(), vec![]),
+                Value::Array("i".into(), vec![]),
+            ]),
+
+            _ => Err((
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                format!(
+                    "theres no {}.{} on {path}",
+                    m.interface.as_deref().unwrap_or_default(),
+                    m.member.as_deref().unwrap_or_default()
+                ),
+            )),
+        }
+    }
+
+    fn properties(&self, path: &str, interface: &str) -> Vec<(&'static str, Value)> {
+        let no_pixmaps = || Value::Array("(iiay)".into(), vec![]);
+
+        match (path, interface) {
+            (ITEM_PATH, ITEM_INTERFACE) => {
+                let pixmaps = (self.item.icon.iter())
+                    .map(|(width, height, pixels)| {
+                        let pixels = pixels.iter().map(|b| Value::Byte(*b)).collect();
+                        Value::Struct(vec![
+                            Value::Int32(*width),
+                            Value::Int32(*height),
+                            Value::Array("y".into(), pixels),
+                        ])
+                    })
+                    .collect();
+
+                vec![
+                    ("Category", Value::str("ApplicationStatus")),
+                    ("Id", Value::str("draw")),
+                    ("Title", Value::str(&self.item.title)),
+                    ("Status", Value::str("Active")),
+                    ("WindowId", Value::Int32(0)),
+                    ("IconName", Value::str("")),
+                    ("IconPixmap", Value::Array("(iiay)".into(), pixmaps)),
+                    ("OverlayIconName", Value::str("")),
+                    ("OverlayIconPixmap", no_pixmaps()),
+                    ("AttentionIconName", Value::str("")),
+                    ("AttentionIconPixmap", no_pixmaps()),
+                    ("AttentionMovieName", Value::str("")),
+                    (
+                        "ToolTip",
+                        Value::Struct(vec![
+                            Value::str(""),
+                            no_pixmaps(),
+                            Value::str(&self.item.title),
+                            Value::str(""),
+                        ]),
+                    ),
+                    ("ItemIsMenu", Value::Bool(false)),
+                    ("Menu", Value::Path(MENU_PATH.into())),
+                ]
+            }
+            (MENU_PATH, MENU_INTERFACE) => vec![
+                ("Version", Value::Uint32(3)),
+                ("TextDirection", Value::str("ltr")),
+                ("Status", Value::str("normal")),
+                ("IconThemePath", Value::Array("s".into(), vec![])),
+            ],
+            _ => vec![],
+        }
+    }
+
+    fn label(&self, id: i32) -> Option<&str> {
+        let index = usize::try_from(id).ok()?.checked_sub(1)?;
+
+        self.item.menu.get(index).map(|(label, _)| label.as_str())
+    }
+
+    /// Menu entries are numbered from 1, 0 is the menu itself
+    fn entry(&self, id: i32) -> Option<Value> {
+        let properties = Value::dict(vec![
+            ("label", Value::str(self.label(id)?)),
+            ("enabled", Value::Bool(true)),
+            ("visible", Value::Bool(true)),
+        ]);
+
+        Some(Value::Struct(vec![
+            Value::Int32(id),
+            properties,
+            Value::Array("v".into(), vec![]),
+        ]))
+    }
+
+    fn root(&self, with_children: bool) -> Value {
+        let children = match with_children {
+            true => (1..=self.item.menu.len() as i32)
+                .filter_map(|id| self.entry(id))
+                .map(Value::variant)
+                .collect(),
+            false => vec![],
+        };
+
+        Value::Struct(vec![
+            Value::Int32(0),
+            Value::dict(vec![("children-display", Value::str("submenu"))]),
+            Value::Array("v".into(), children),
+        ])
+    }
+
+    fn clicked(&self, id: i32, event: &str) {
+        if event != "clicked" {
+            return;
+        }
+
+        if let Some(index) = usize::try_from(id).ok().and_then(|id| id.checked_sub(1)) {
+            if let Some((_, event)) = self.item.menu.get(index) {
+                (self.send)(*event);
+            }
+        }
+    }
+}
+
+fn unknown_entry(id: i32) -> (&'static str, String) {
+    (
+        "org.freedesktop.DBus.Error.InvalidArgs",
+        format!("theres no menu entry {id}"),
+    )
+}
+
+/// Some panels check whats there before asking for it
+fn introspect(path: &str) -> String {
+    let interface = match path {
+        ITEM_PATH => ITEM_INTERFACE,
+        MENU_PATH => MENU_INTERFACE,
+        _ => {
+            return format!(
+                "<node><node name=\"{}\"/><node name=\"{}\"/></node>",
+                &ITEM_PATH[1..],
+                &MENU_PATH[1..]
+            )
+        }
+    };
+
+    format!(
+        "<node><interface name=\"{INTROSPECTABLE}\"/><interface name=\"{PROPERTIES}\"/>\
+         <interface name=\"{interface}\"/></node>"
+    )
+}
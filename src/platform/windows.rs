@@ -0,0 +1,186 @@
+use std::{error::Error, path::PathBuf};
+
+use trayicon::{MenuBuilder, TrayIcon, TrayIconBuilder};
+use windows::{
+    core::w,
+    Win32::{
+        Foundation::{GlobalFree, HANDLE, HWND},
+        System::{
+            DataExchange::{
+                CloseClipboard, EmptyClipboard, OpenClipboard, RegisterClipboardFormatW,
+                SetClipboardData,
+            },
+            Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+        },
+        UI::{
+            Input::Pointer::{GetPointerPenInfo, POINTER_PEN_INFO},
+            WindowsAndMessaging::{
+                PEN_FLAG_ERASER, PEN_FLAG_INVERTED, PEN_MASK_TILT_X, PEN_MASK_TILT_Y,
+            },
+        },
+    },
+};
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::EventLoop,
+    platform::windows::WindowAttributesExtWindows,
+    window::{Window, WindowAttributes, WindowLevel},
+};
+
+use super::PenInfo;
+use crate::app::apphandler::{TrayEvent, UserEvent};
+use crate::modules::export;
+
+/// Standard clipboard format id, not worth pulling in all of ole for
+const CF_DIB: u32 = 8;
+
+pub struct Tray {
+    icon: TrayIcon<UserEvent>,
+}
+
+impl Tray {
+    pub fn new(event_loop: &EventLoop<UserEvent>) -> Result<Self, Box<dyn Error>> {
+        let icon = include_bytes!("../../assets/pencil.ico");
+        let proxy = event_loop.create_proxy();
+
+        let icon = TrayIconBuilder::new()
+            .sender(move |e: &UserEvent| {
+                let _ = proxy.send_event(*e);
+            })
+            .icon_from_buffer(icon)
+            .on_click(TrayEvent::Show.into())
+            .on_right_click(TrayEvent::RightClick.into())
+            .menu(
+                MenuBuilder::new()
+                    .item("&Show", TrayEvent::Show.into())
+                    .item("E&xit", TrayEvent::Exit.into()),
+            )
+            .build()?;
+
+        Ok(Self { icon })
+    }
+
+    pub fn show_menu(&mut self) -> Result<(), Box<dyn Error>> {
+        self.icon.show_menu()?;
+
+        Ok(())
+    }
+
+    pub fn is_shown(&self) -> bool {
+        true
+    }
+}
+
+pub fn window_attributes(attributes: WindowAttributes) -> WindowAttributes {
+    attributes
+        .with_window_level(WindowLevel::AlwaysOnTop)
+        .with_skip_taskbar(true)
+}
+
+/// Raw keyboard input always reaches us on windows
+pub fn global_keys<T>(_display: &T) -> bool {
+    true
+}
+
+pub fn show_window(window: &Window) {
+    window.set_minimized(false);
+}
+
+pub fn hide_window(window: &Window) {
+    window.set_minimized(true);
+}
+
+/// winit hands out the pointer id as the touch id, so we can ask windows about the rest
+pub fn pen_info(touch_id: u64) -> Option<PenInfo> {
+    let mut info = POINTER_PEN_INFO::default();
+    unsafe { GetPointerPenInfo(touch_id as u32, &mut info) }.ok()?;
+
+    let eraser = info.penFlags & (PEN_FLAG_INVERTED | PEN_FLAG_ERASER) != 0;
+    let tilt = (info.penMask & (PEN_MASK_TILT_X | PEN_MASK_TILT_Y) != 0)
+        .then_some((info.tiltX as f32, info.tiltY as f32));
+
+    Some(PenInfo { eraser, tilt })
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("USERPROFILE").map(PathBuf::from)
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(PathBuf::from)
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+}
+
+pub fn pictures_dir() -> Option<PathBuf> {
+    std::env::var_os("USERPROFILE").map(|home| PathBuf::from(home).join("Pictures"))
+}
+
+/// Puts the image on the clipboard twice, as a png for apps that keep the transparency
+/// and as a plain bitmap for everything else
+pub fn copy_image(pixels: &[u32], size: PhysicalSize<u32>) -> Result<(), Box<dyn Error>> {
+    let png = export::encode_png(pixels, size);
+    let dib = dib(pixels, size);
+
+    unsafe {
+        OpenClipboard(HWND(0))?;
+
+        let copied = EmptyClipboard()
+            .and_then(|_| set_clipboard(RegisterClipboardFormatW(w!("PNG")), &png))
+            .and_then(|_| set_clipboard(CF_DIB, &dib));
+
+        let _ = CloseClipboard();
+        copied?;
+    }
+
+    Ok(())
+}
+
+/// The clipboard takes ownership of the memory, unless setting it fails
+unsafe fn set_clipboard(format: u32, data: &[u8]) -> windows::core::Result<()> {
+    let memory = GlobalAlloc(GMEM_MOVEABLE, data.len())?;
+
+    let ptr = GlobalLock(memory) as *mut u8;
+    if ptr.is_null() {
+        let _ = GlobalFree(memory);
+        return Err(windows::core::Error::from_win32());
+    }
+
+    std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+    // returns an "error" once the lock count hits zero, which is what we want
+    let _ = GlobalUnlock(memory);
+
+    if let Err(e) = SetClipboardData(format, HANDLE(memory.0 as isize)) {
+        let _ = GlobalFree(memory);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// BITMAPINFOHEADER followed by bottom up BGRA rows, most apps ignore the alpha so the colors are straight
+fn dib(pixels: &[u32], size: PhysicalSize<u32>) -> Vec<u8> {
+    let rgba = export::to_rgba(pixels);
+    let mut dib = Vec::with_capacity(40 + rgba.len());
+
+    dib.extend_from_slice(&40u32.to_le_bytes());
+    dib.extend_from_slice(&(size.width as i32).to_le_bytes());
+    dib.extend_from_slice(&(size.height as i32).to_le_bytes());
+    // 1 plane, 32 bits per pixel, BI_RGB
+    dib.extend_from_slice(&1u16.to_le_bytes());
+    dib.extend_from_slice(&32u16.to_le_bytes());
+    dib.extend_from_slice(&0u32.to_le_bytes());
+    dib.extend_from_slice(&(rgba.len() as u32).to_le_bytes());
+    // resolution and palette, all unused
+    dib.extend_from_slice(&[0; 16]);
+
+    for row in rgba.chunks(size.width.max(1) as usize * 4).rev() {
+        for px in row.chunks(4) {
+            dib.extend_from_slice(&[px[2], px[1], px[0], px[3]]);
+        }
+    }
+
+    dib
+}